@@ -0,0 +1,21 @@
+use std::env;
+
+/// Checks the key of the secret codes, which must stay out of the sources.
+/// Without it the game uses a public development key, fine for testing only
+fn main() {
+    println!("cargo::rerun-if-env-changed=MERINO_CODE_KEY");
+    match env::var("MERINO_CODE_KEY") {
+        Ok(key) if key.len() < 16 => {
+            panic!("MERINO_CODE_KEY must have at least 16 characters");
+        }
+        Ok(_) => {}
+        Err(_) => {
+            let release = env::var("PROFILE").is_ok_and(|profile| profile == "release");
+            let warning = match release {
+                true => "MERINO_CODE_KEY is not set, anyone can forge the codes of this release",
+                false => "MERINO_CODE_KEY is not set, codes use the development key",
+            };
+            println!("cargo::warning={warning}");
+        }
+    }
+}
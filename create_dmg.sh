@@ -1,3 +1,4 @@
+# The release bundle must be built with MERINO_CODE_KEY set, see README
 
 ln -sf ../Resources/assets target/release/bundle/osx/MerinoBreakout.app/Contents/MacOS/
 hdiutil create -volname "MerinoBreakout" -srcfolder "target/release/bundle/osx/MerinoBreakout.app" -ov -format UDZO "MerinoBreakout.dmg"
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
-use crate::game::*;
+use crate::consts::*;
+use crate::game::GameTag;
 use bevy::prelude::*;
 
 pub enum AnimationType {
@@ -23,9 +24,9 @@ pub struct Animation {
 pub fn animate(
     mut commands: Commands,
     mut query: Query<(Entity, &mut Sprite, &mut Transform, &mut Animation)>,
-    game: Res<Game>,
+    time: Res<Time>,
 ) {
-    let dt = game.avg_delta;
+    let dt = time.delta_secs();
     for (anim_entity, mut sprite, mut transform, mut anim) in &mut query {
         // Move sprite
         transform.translation.x += anim.velocity.x * dt;
@@ -62,3 +63,21 @@ pub fn animate(
         }
     }
 }
+
+/// Spawns an impact or explosion animation that despawns when finished
+pub fn spawn_explosion(commands: &mut Commands, frames: &[Handle<Image>], pos: Vec2) {
+    commands.spawn((
+        Sprite::from_image(frames[0].clone()),
+        Transform::from_xyz(pos.x, pos.y, LAYER_EXPLOSIONS),
+        Animation {
+            timer: Timer::from_seconds(1.0 / BRICK_FRAMERATE, TimerMode::Repeating),
+            frozen: false,
+            variant: AnimationType::Despawn,
+            frames: frames.to_vec(),
+            current_frame: 0,
+            reverse: false,
+            velocity: Vec2::ZERO,
+        },
+        GameTag,
+    ));
+}
@@ -0,0 +1,140 @@
+use crate::consts::*;
+use crate::settings::*;
+
+use bevy::audio::SpatialScale;
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Groups of sounds with their own volume in the settings
+pub enum AudioBus {
+    Music,
+    Sfx,
+    Ui,
+}
+
+#[derive(Component)]
+/// Sound being played, whose volume follows the settings of its bus
+pub struct Voice {
+    pub bus: AudioBus,
+    /// Volume of the sound before the settings are applied
+    pub volume: f32,
+    sound: AssetId<AudioSource>,
+}
+
+#[derive(Resource, Default)]
+/// Volumes of the buses, and times when each sound was last started to space repeated hits
+pub struct Mixer {
+    /// Copy of the settings, so that sounds can be played while the settings are changed
+    settings: Settings,
+    last_played: HashMap<AssetId<AudioSource>, f32>,
+}
+
+#[derive(SystemParam)]
+/// Plays sounds on the buses, limiting the voices of each sound
+pub struct Audio<'w, 's> {
+    commands: Commands<'w, 's>,
+    mixer: ResMut<'w, Mixer>,
+    time: Res<'w, Time<Real>>,
+    voices: Query<'w, 's, &'static Voice>,
+}
+
+impl Audio<'_, '_> {
+    /// Plays a sound once. It is dropped if it was started too recently
+    /// or too many copies of it are playing
+    pub fn play(&mut self, bus: AudioBus, sound: &Handle<AudioSource>) {
+        if !self.can_play(sound) {
+            return;
+        }
+        self.commands.spawn((
+            AudioPlayer::new(sound.clone()),
+            PlaybackSettings::DESPAWN.with_volume(self.mixer.settings.volume(bus, 1.)),
+            Voice {
+                bus,
+                volume: 1.,
+                sound: sound.id(),
+            },
+        ));
+    }
+
+    /// Plays a sound once, panned to the x position of the game area where it
+    /// happened and played faster (higher) by `speed`
+    pub fn play_at(&mut self, bus: AudioBus, sound: &Handle<AudioSource>, x: f32, speed: f32) {
+        if !self.can_play(sound) {
+            return;
+        }
+        self.commands.spawn((
+            AudioPlayer::new(sound.clone()),
+            PlaybackSettings {
+                speed,
+                spatial: true,
+                spatial_scale: Some(SpatialScale::new(2. / GAMEAREA_WIDTH)),
+                ..PlaybackSettings::DESPAWN
+            }
+            .with_volume(self.mixer.settings.volume(bus, AUDIO_PAN_GAIN)),
+            Transform::from_xyz(x, AUDIO_PAN_DEPTH, 0.),
+            Voice {
+                bus,
+                volume: AUDIO_PAN_GAIN,
+                sound: sound.id(),
+            },
+        ));
+    }
+
+    /// Checks the cooldown and voices of a sound, and counts it as started if it can play
+    fn can_play(&mut self, sound: &Handle<AudioSource>) -> bool {
+        let now = self.time.elapsed_secs();
+        let id = sound.id();
+        if let Some(last) = self.mixer.last_played.get(&id)
+            && now - last < AUDIO_COOLDOWN_SECS
+        {
+            return false;
+        }
+        let playing = self.voices.iter().filter(|v| v.sound == id).count();
+        if playing >= AUDIO_MAX_VOICES {
+            return false;
+        }
+        self.mixer.last_played.insert(id, now);
+        true
+    }
+
+    /// Plays a music in a loop. The caller tags it to despawn it with its screen
+    pub fn music(&mut self, sound: &Handle<AudioSource>, volume: f32) -> EntityCommands<'_> {
+        self.commands.spawn((
+            AudioPlayer::new(sound.clone()),
+            PlaybackSettings::LOOP.with_volume(self.mixer.settings.volume(AudioBus::Music, volume)),
+            Voice {
+                bus: AudioBus::Music,
+                volume,
+                sound: sound.id(),
+            },
+        ))
+    }
+}
+
+pub fn audio_plugin(app: &mut App) {
+    app.init_resource::<Mixer>()
+        .add_systems(Startup, audio_listener)
+        .add_systems(Update, audio_volume.run_if(resource_changed::<Settings>));
+}
+
+/// Places the ears at both sides of the game area, for panned sounds
+fn audio_listener(mut commands: Commands) {
+    commands.spawn((
+        SpatialListener::new(GAMEAREA_WIDTH),
+        Transform::from_xyz(GAMEAREA_CENTER_X, 0., 0.),
+    ));
+}
+
+/// Applies the volume settings to the sounds being played
+fn audio_volume(
+    settings: Res<Settings>,
+    mut mixer: ResMut<Mixer>,
+    mut voices: Query<(&Voice, &mut AudioSink)>,
+) {
+    mixer.settings = settings.clone();
+    for (voice, mut sink) in &mut voices {
+        sink.set_volume(settings.volume(voice.bus, voice.volume));
+    }
+}
@@ -1,31 +1,52 @@
 use crate::animation::*;
-use crate::balls_and_bullets::*;
-use crate::collisions::*;
-use crate::countdown_and_portal::*;
 use crate::game::*;
-use crate::paddle::*;
 use crate::*;
-use bevy::math::*;
 use bevy::prelude::*;
-use rand::distr::Distribution;
-use std::time::Duration;
 
 #[derive(Component)]
+/// Component for power-up barrels, mirroring a simulated barrel
 pub struct Barrel {
-    variant: usize,
+    /// Identifier of the simulated barrel
+    pub id: u32,
 }
 
-pub fn barrels_spawn(commands: &mut Commands, mut translation: Vec3, game: &Game) {
-    if rand::random_bool(BARREL_CHANCE) {
-        let variant: usize = game.barrel_dist.sample(&mut rand::rng());
-        translation.z = LAYER_BARRELS;
+#[derive(Component)]
+/// Component for the icon and the seconds left of an active barrel effect
+pub struct EffectIcon {
+    /// Position in the list of simulated effects
+    pub slot: usize,
+}
+
+/// System that keeps barrel sprites in sync with the simulated barrels
+pub fn barrels_sync(
+    mut commands: Commands,
+    mut barrels: Query<(Entity, &mut Transform, &Barrel)>,
+    sim: Res<Simulation>,
+    game: Res<Game>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    // Interpolate between the last two simulation steps
+    let alpha = fixed_time.overstep_fraction();
+    for (barrel_entity, mut barrel_tr, barrel) in &mut barrels {
+        match sim.barrels.iter().find(|b| b.id == barrel.id) {
+            Some(sim_barrel) => {
+                let pos = sim_barrel.prev_pos.lerp(sim_barrel.pos, alpha);
+                barrel_tr.translation.x = pos.x;
+                barrel_tr.translation.y = pos.y;
+            }
+            None => commands.entity(barrel_entity).despawn(),
+        }
+    }
+
+    for sim_barrel in &sim.barrels {
+        if barrels.iter().any(|(_, _, b)| b.id == sim_barrel.id) {
+            continue;
+        }
+        let variant = sim_barrel.variant;
         let barrel = commands
             .spawn((
                 Sprite::from_image(game.h_barrel_frames[variant][0].clone()),
-                Transform {
-                    translation,
-                    ..default()
-                },
+                Transform::from_xyz(sim_barrel.pos.x, sim_barrel.pos.y, LAYER_BARRELS),
                 Animation {
                     timer: Timer::from_seconds(1.0 / BARREL_FRAMERATE, TimerMode::Repeating),
                     frozen: false,
@@ -33,9 +54,9 @@ pub fn barrels_spawn(commands: &mut Commands, mut translation: Vec3, game: &Game
                     frames: game.h_barrel_frames[variant].clone(),
                     current_frame: 0,
                     reverse: false,
-                    velocity: Vec2::new(0., -BARREL_SPEED),
+                    velocity: Vec2::ZERO,
                 },
-                Barrel { variant },
+                Barrel { id: sim_barrel.id },
                 GameTag,
             ))
             .id();
@@ -49,129 +70,51 @@ pub fn barrels_spawn(commands: &mut Commands, mut translation: Vec3, game: &Game
     }
 }
 
-pub fn barrels_update(
+/// System that shows the active barrel effects and their remaining time in the info area
+pub fn effects_sync(
     mut commands: Commands,
-    barrel_query: Query<(Entity, &Transform, &Barrel)>,
-    mut balls: Query<(Entity, &Transform, &mut Ball)>,
-    paddle_comp: Single<(&mut Sprite, &Transform, &mut Paddle), Without<PaddleShadow>>,
-    shadow_comp: Single<&mut Sprite, With<PaddleShadow>>,
-    countdown_comp: Single<&mut Countdown>,
-    msg_comp: Single<(&mut Text2d, &mut InfoAreaMsg)>,
-    mut game: ResMut<Game>,
+    mut icons: Query<(Entity, &EffectIcon, Option<&mut Sprite>, Option<&mut Text2d>)>,
+    sim: Res<Simulation>,
+    game: Res<Game>,
 ) {
-    let mut countdown = countdown_comp.into_inner();
-    let (mut sprite, paddle_tr, mut paddle) = paddle_comp.into_inner();
-    let paddle_size = PADDLE_SIZES[paddle.variant];
-    let mut new_paddle_variant: usize = paddle.variant;
-    let mut multiball: u8 = 0;
-    let mut delta_speed: f32 = 1.0;
-    let mut barrel_collisions: u8 = 0;
-    let (mut text, mut text_timer) = msg_comp.into_inner();
-    for (barrel_entity, barrel_tr, barrel) in &barrel_query {
-        if collision(
-            Body::Rectangular(barrel_tr.translation.truncate(), BARREL_SIZE),
-            paddle_tr.translation.truncate(),
-            paddle_size,
-        )
-        .is_some()
-        {
-            match barrel.variant {
-                0 => new_paddle_variant = PADDLE_LARGE,
-                1 => new_paddle_variant = PADDLE_GUN,
-                2 => new_paddle_variant = PADDLE_SMALL,
-                3 => new_paddle_variant = PADDLE_MAGNET,
-                4 => multiball += 1,
-                5 => delta_speed *= BALL_SPEED_DELTA,
-                6 => delta_speed /= BALL_SPEED_DELTA,
-                7 => {} // Portal
-                8 => {} // Extra Life
-                9 => {
-                    // Extra Time
-                    let remaining: u64 = countdown.timer.remaining_secs().ceil() as u64;
-                    if remaining > 0 {
-                        let duration = countdown.timer.duration();
-                        let extra = Duration::from_secs(30);
-                        countdown.timer.set_duration(duration + extra);
-                    }
-                }
-                _ => {}
+    let mut nshown = 0;
+    for (icon_entity, icon, sprite, text) in &mut icons {
+        let Some(effect) = sim.effects.get(icon.slot) else {
+            commands.entity(icon_entity).despawn();
+            continue;
+        };
+        if let Some(mut sprite) = sprite {
+            let image = &game.h_barrel_frames[effect.variant][0];
+            if sprite.image != *image {
+                sprite.image = image.clone();
             }
-            *text = Text2d::new(BARREL_TITLES[barrel.variant]);
-            text_timer.timer.reset();
-            barrel_collisions += 1;
-            commands.entity(barrel_entity).despawn();
-            commands.spawn((
-                AudioPlayer::new(game.sound_barrels[barrel.variant].clone()),
-                PlaybackSettings::DESPAWN,
-            ));
-        } else if barrel_tr.translation.y < GAMEAREA_MINY {
-            commands.entity(barrel_entity).despawn();
+            nshown += 1;
         }
-    }
-
-    if barrel_collisions == 0 {
-        return;
-    }
-
-    // Update paddle
-    if new_paddle_variant != paddle.variant {
-        let mut sprite_shadow = shadow_comp.into_inner();
-        paddle.variant = new_paddle_variant;
-        *sprite = Sprite::from_image(game.h_paddles[new_paddle_variant].clone());
-        *sprite_shadow = Sprite::from_image(game.h_paddle_shadows[new_paddle_variant].clone());
-    }
-
-    // Update balls
-    for (ball_entity, ball_tr, mut ball) in &mut balls {
-        // Update speed
-        if delta_speed != 1.0 {
-            ball.speed = (ball.speed * delta_speed).clamp(BALL_MIN_SPEED, BALL_MAX_SPEED);
-            ball.impacts = 0;
-            // Only the speed of the last ball will be seen but that's OK
-            *text = Text2d::new(format!(
-                "Speed {}%",
-                (ball.speed / BALL_INITIAL_SPEED * 100.).round()
-            ));
-        }
-
-        // unmagnetize if paddle was changed
-        if paddle.variant != PADDLE_MAGNET {
-            ball.caught = false;
-        }
-
-        // Spawn multiball event
-        let mut m = multiball;
-        while m > 0 && !ball.caught && game.nballs < MULTIBALL_MAX - 1 {
-            m -= 1;
-            // Spawn 3 new balls
-            for _ in 0..3 {
-                let direction = nudge_ball(ball.direction, MULTIBALL_ANGLE_RANGE);
-                let new_ball = commands
-                    .spawn((
-                        Sprite::from_image(game.h_ball.clone()),
-                        *ball_tr,
-                        Ball {
-                            impacts: 0,
-                            impacts_since_paddle: 0,
-                            speed: ball.speed,
-                            direction,
-                            caught: false,
-                            in_collision: false,
-                        },
-                        GameTag,
-                    ))
-                    .id();
-                let ball_shadow = commands
-                    .spawn((
-                        Sprite::from_image(game.h_ball_shadow.clone()),
-                        Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS - LAYER_BALL),
-                    ))
-                    .id();
-                commands.entity(new_ball).add_child(ball_shadow);
+        if let Some(mut text) = text {
+            let seconds = format!("{:.0}", effect.time_left.ceil());
+            if text.0 != seconds {
+                text.0 = seconds;
             }
-            // Despawn old ball
-            commands.entity(ball_entity).despawn();
-            game.nballs += 2;
         }
     }
+
+    for (slot, effect) in sim.effects.iter().enumerate().skip(nshown) {
+        let y = INFOAREA_EFFECTS_Y - slot as f32 * INFOAREA_EFFECTS_DY;
+        commands.spawn((
+            Sprite::from_image(game.h_barrel_frames[effect.variant][0].clone()),
+            Transform::from_xyz(INFOAREA_CENTER_X - 20., y, LAYER_BANNER),
+            EffectIcon { slot },
+            GameTag,
+        ));
+        commands.spawn((
+            Text2d::new(format!("{:.0}", effect.time_left.ceil())),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            Transform::from_xyz(INFOAREA_CENTER_X + 25., y, LAYER_BANNER),
+            EffectIcon { slot },
+            GameTag,
+        ));
+    }
 }
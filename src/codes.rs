@@ -0,0 +1,471 @@
+use crate::consts::*;
+
+use ciphers::{Cipher, Vigenere};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+/// Reasons to refuse a code, shown in the shop
+pub enum CodeError {
+    #[error("Codes have {CODE_LEN} characters, not {0}")]
+    WrongLength(usize),
+    #[error("Codes cannot have '{0}'")]
+    InvalidCharacter(char),
+    #[error("Wrong code, check for typos")]
+    BadChecksum,
+    #[error("Unknown kind of code")]
+    UnknownType,
+    #[error("This code belongs to another player")]
+    NotForThisPlayer,
+    #[error("Secret not discovered yet")]
+    NotDiscovered,
+    #[error("Duplicated")]
+    Duplicate,
+    #[error("Codes of older versions are not accepted")]
+    Legacy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Where a valid code is kept in the player's progress
+pub enum CodeSlot {
+    /// Secret discovered by this player
+    Discovered(usize),
+    /// Secret discovered by another player
+    Unlocked(usize),
+    /// Level unlocked by this player
+    Level(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// What a code unlocks
+pub enum CodeKind {
+    /// Secret discovered by a player, shared to unlock it for others ('X')
+    Secret,
+    /// Level whose portal stays open ('L')
+    Level,
+}
+
+impl CodeKind {
+    fn letter(&self) -> char {
+        match self {
+            CodeKind::Secret => 'X',
+            CodeKind::Level => 'L',
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+/// Content of a secret or level code
+pub struct Code {
+    /// Player who earned the code
+    pub player_id: String,
+    pub kind: CodeKind,
+    /// Index of the secret or level
+    pub idx: usize,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let idx = (b'A' + self.idx as u8) as char;
+        write!(f, "{}{}{idx}", self.player_id, self.kind.letter())
+    }
+}
+
+impl Code {
+    /// Code in the current format: version, payload, MAC and check character
+    pub fn encode(&self) -> String {
+        let payload = self.payload();
+        let mut code = String::from(CODE_VERSION);
+        push_base32(&mut code, payload, CODE_PAYLOAD_LEN);
+        push_base32(&mut code, code_mac(payload), CODE_MAC_LEN);
+        code.push(CODE_ALPHABET[check_digit(&code)] as char);
+        code
+    }
+
+    /// Reads a code in the current format
+    pub fn parse(code: &str) -> Result<Code, CodeError> {
+        let code = code.trim().to_ascii_uppercase();
+        if Code::parse_legacy(&code).is_ok() {
+            return Err(CodeError::Legacy);
+        }
+        // Letters that look like digits are read as those digits
+        let digits = code
+            .chars()
+            .map(|c| match c {
+                'I' | 'L' => Ok(1),
+                'O' => Ok(0),
+                c => CODE_ALPHABET
+                    .iter()
+                    .position(|a| *a as char == c)
+                    .ok_or(CodeError::InvalidCharacter(c)),
+            })
+            .collect::<Result<Vec<usize>, CodeError>>()?;
+        if digits.len() != CODE_LEN {
+            return Err(CodeError::WrongLength(digits.len()));
+        }
+        if !code.starts_with(CODE_VERSION) {
+            return Err(CodeError::UnknownType);
+        }
+        let canonical: String = digits.iter().map(|d| CODE_ALPHABET[*d] as char).collect();
+        let (body, check) = canonical.split_at(CODE_LEN - 1);
+        if CODE_ALPHABET[check_digit(body)] != check.as_bytes()[0] {
+            return Err(CodeError::BadChecksum);
+        }
+
+        let payload_end = 1 + CODE_PAYLOAD_LEN;
+        let payload = base32_value(&digits[1..payload_end]);
+        let mac = base32_value(&digits[payload_end..CODE_LEN - 1]);
+        if mac != code_mac(payload) {
+            return Err(CodeError::BadChecksum);
+        }
+        Code::from_payload(payload)
+    }
+
+    /// Vigenère codes of older versions, which anyone can forge. Only read from saves
+    /// written before the current format, to convert them
+    pub fn parse_legacy(code: &str) -> Result<Code, CodeError> {
+        let code = code.trim().to_ascii_uppercase();
+        if let Some(c) = code.chars().find(|c| !c.is_ascii_uppercase()) {
+            return Err(CodeError::InvalidCharacter(c));
+        }
+        if code.len() != LEGACY_CODE_LEN {
+            return Err(CodeError::WrongLength(code.len()));
+        }
+        let expected_crc = code.as_bytes()[code.len() - 1];
+        let vigenere = Vigenere::new(&legacy_key(expected_crc));
+        let decoded = vigenere
+            .decipher(&code[..code.len() - 1])
+            .map_err(|_| CodeError::BadChecksum)?;
+        if decoded.len() != LEGACY_CODE_LEN - 1 || legacy_crc(&decoded) != expected_crc {
+            return Err(CodeError::BadChecksum);
+        }
+        let bytes = decoded.as_bytes();
+        let kind = match bytes[PLAYER_ID_LEN] {
+            b'X' => CodeKind::Secret,
+            b'L' => CodeKind::Level,
+            _ => return Err(CodeError::UnknownType),
+        };
+        let idx = match bytes[PLAYER_ID_LEN + 1].checked_sub(b'A') {
+            Some(idx) if (idx as usize) < MAX_LEVELS => idx as usize,
+            _ => return Err(CodeError::UnknownType),
+        };
+        Ok(Code {
+            player_id: decoded[..PLAYER_ID_LEN].to_string(),
+            kind,
+            idx,
+        })
+    }
+
+    /// Checks a code against the progress of a player. Codes of other players
+    /// only unlock secrets that this player discovered too
+    pub fn slot(
+        &self,
+        player_id: &str,
+        discovered: &[String],
+        unlocked: &[String],
+    ) -> Result<CodeSlot, CodeError> {
+        let idx = self.idx;
+        match self.kind {
+            CodeKind::Secret if idx >= NSECRETS => Err(CodeError::UnknownType),
+            CodeKind::Secret if self.player_id == player_id => Ok(CodeSlot::Discovered(idx)),
+            CodeKind::Level if self.player_id == player_id => Ok(CodeSlot::Level(idx)),
+            CodeKind::Level => Err(CodeError::NotForThisPlayer),
+            CodeKind::Secret if discovered.get(idx).is_none_or(String::is_empty) => {
+                Err(CodeError::NotDiscovered)
+            }
+            CodeKind::Secret if unlocked.get(idx).is_some_and(|s| !s.is_empty()) => {
+                Err(CodeError::Duplicate)
+            }
+            CodeKind::Secret => Ok(CodeSlot::Unlocked(idx)),
+        }
+    }
+
+    /// Player ID as a base 26 number, followed by one bit for the kind and the index
+    fn payload(&self) -> u64 {
+        let id = self
+            .player_id
+            .bytes()
+            .fold(0, |id, c| id * 26 + (c - b'A') as u64);
+        let kind = match self.kind {
+            CodeKind::Secret => 0,
+            CodeKind::Level => 1,
+        };
+        (id << (CODE_IDX_BITS + 1)) | (kind << CODE_IDX_BITS) | self.idx as u64
+    }
+
+    fn from_payload(payload: u64) -> Result<Code, CodeError> {
+        let idx = (payload & ((1 << CODE_IDX_BITS) - 1)) as usize;
+        let kind = match (payload >> CODE_IDX_BITS) & 1 {
+            0 => CodeKind::Secret,
+            _ => CodeKind::Level,
+        };
+        let mut id = payload >> (CODE_IDX_BITS + 1);
+        let mut player_id = String::new();
+        for _ in 0..PLAYER_ID_LEN {
+            player_id.insert(0, (b'A' + (id % 26) as u8) as char);
+            id /= 26;
+        }
+        if id != 0 || idx >= MAX_LEVELS {
+            return Err(CodeError::UnknownType);
+        }
+        Ok(Code {
+            player_id,
+            kind,
+            idx,
+        })
+    }
+}
+
+/// Keyed BLAKE3 hash of the version and payload, cut to the bits that fit in the code
+fn code_mac(payload: u64) -> u64 {
+    let mut input = vec![CODE_VERSION as u8];
+    input.extend_from_slice(&payload.to_be_bytes());
+    let hash = blake3::keyed_hash(&code_key(), &input);
+    let bits = hash.as_bytes()[..8]
+        .iter()
+        .fold(0, |bits, b| (bits << 8) | *b as u64);
+    bits >> (64 - 5 * CODE_MAC_LEN)
+}
+
+/// Key of the MACs, derived from the secret given at build time
+fn code_key() -> [u8; 32] {
+    let secret = option_env!("MERINO_CODE_KEY").unwrap_or(CODE_DEV_KEY);
+    blake3::derive_key(CODE_KEY_CONTEXT, secret.as_bytes())
+}
+
+/// Appends the lowest `len` base 32 digits of a number, most significant first
+fn push_base32(code: &mut String, value: u64, len: usize) {
+    for i in (0..len).rev() {
+        code.push(CODE_ALPHABET[((value >> (5 * i)) & 31) as usize] as char);
+    }
+}
+
+fn base32_value(digits: &[usize]) -> u64 {
+    digits.iter().fold(0, |value, d| (value << 5) | *d as u64)
+}
+
+/// Luhn mod 32 check digit, which catches any mistyped character
+/// and most swaps of two neighbours
+fn check_digit(code: &str) -> usize {
+    let sum: usize = code
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let digit = CODE_ALPHABET.iter().position(|a| *a == c).unwrap_or(0);
+            let addend = if i % 2 == 0 { 2 * digit } else { digit };
+            addend / 32 + addend % 32
+        })
+        .sum();
+    (32 - sum % 32) % 32
+}
+
+/// Vigenère key of legacy codes, rotated by their CRC letter
+fn legacy_key(crc: u8) -> String {
+    let rotation = crc as usize % CIPHER_KEY.len();
+    format!("{}{}", &CIPHER_KEY[rotation..], &CIPHER_KEY[..rotation])
+}
+
+fn legacy_crc(input: &str) -> u8 {
+    let mut crc: u8 = 0;
+    for c in input.chars() {
+        crc = crc.wrapping_add(c as u8);
+        crc <<= 1;
+    }
+    // Convert CRC to uppercase letter
+    b'A' + (crc % 26)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_code(rng: &mut StdRng) -> Code {
+        Code {
+            player_id: (0..PLAYER_ID_LEN)
+                .map(|_| rng.random_range('A'..='Z'))
+                .collect(),
+            kind: if rng.random() {
+                CodeKind::Secret
+            } else {
+                CodeKind::Level
+            },
+            idx: rng.random_range(0..MAX_LEVELS),
+        }
+    }
+
+    #[test]
+    fn encoded_codes_parse_back() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..2000 {
+            let code = random_code(&mut rng);
+            let encoded = code.encode();
+            assert_eq!(encoded.len(), CODE_LEN);
+            assert!(encoded.starts_with(CODE_VERSION));
+            assert!(encoded.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+            assert_eq!(Code::parse(&encoded), Ok(code.clone()));
+            assert_eq!(Code::parse(&encoded.to_lowercase()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn lookalike_letters_read_as_digits() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..200 {
+            let code = random_code(&mut rng);
+            let typed = code.encode()[1..].replace('1', "L").replace('0', "O");
+            assert_eq!(Code::parse(&format!("{CODE_VERSION}{typed}")), Ok(code));
+        }
+    }
+
+    #[test]
+    fn mistyped_characters_are_caught() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let encoded = random_code(&mut rng).encode();
+            for pos in 1..CODE_LEN {
+                for c in CODE_ALPHABET
+                    .iter()
+                    .filter(|c| **c != encoded.as_bytes()[pos])
+                {
+                    let mut typo = encoded.clone().into_bytes();
+                    typo[pos] = *c;
+                    let typo = String::from_utf8(typo).unwrap();
+                    assert_eq!(Code::parse(&typo), Err(CodeError::BadChecksum), "{typo}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swapped_characters_are_caught() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..500 {
+            let encoded = random_code(&mut rng).encode();
+            for pos in 1..CODE_LEN - 1 {
+                let mut swap = encoded.clone().into_bytes();
+                if swap[pos] == swap[pos + 1] {
+                    continue;
+                }
+                swap.swap(pos, pos + 1);
+                let swap = String::from_utf8(swap).unwrap();
+                assert!(Code::parse(&swap).is_err(), "{swap}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_input_never_panics() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let chars: Vec<char> = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcxyz -_*é€\u{0}"
+            .chars()
+            .collect();
+        for _ in 0..20000 {
+            let len = rng.random_range(0..2 * CODE_LEN);
+            let mut input: String = (0..len)
+                .map(|_| chars[rng.random_range(0..chars.len())])
+                .collect();
+            if rng.random() {
+                input.insert(0, CODE_VERSION);
+            }
+            if let Ok(code) = Code::parse(&input) {
+                assert!(code.idx < MAX_LEVELS);
+                assert_eq!(code.player_id.len(), PLAYER_ID_LEN);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_codes_give_their_error() {
+        assert_eq!(Code::parse(""), Err(CodeError::WrongLength(0)));
+        assert_eq!(Code::parse("2ABC"), Err(CodeError::WrongLength(4)));
+        assert_eq!(
+            Code::parse("2UUUUUUUUUUUU"),
+            Err(CodeError::InvalidCharacter('U'))
+        );
+        assert_eq!(
+            Code::parse_legacy("ABCD1FGHI"),
+            Err(CodeError::InvalidCharacter('1'))
+        );
+        let out_of_range = Code {
+            player_id: "AAAAAA".to_string(),
+            kind: CodeKind::Level,
+            idx: MAX_LEVELS,
+        };
+        assert_eq!(
+            Code::parse(&out_of_range.encode()),
+            Err(CodeError::UnknownType)
+        );
+        let other_version = format!("3{}", &out_of_range.encode()[1..]);
+        assert_eq!(Code::parse(&other_version), Err(CodeError::UnknownType));
+    }
+
+    #[test]
+    fn legacy_codes_only_parse_for_conversion() {
+        let felipe = |kind, idx| Code {
+            player_id: "FELIPE".to_string(),
+            kind,
+            idx,
+        };
+        let legacy = Code::parse_legacy;
+        assert_eq!(legacy("NPEAZMYRS"), Ok(felipe(CodeKind::Level, 0)));
+        assert_eq!(legacy("ywvqcvfnu"), Ok(felipe(CodeKind::Level, 1)));
+        assert_eq!(legacy("QIDBXPQSO"), Ok(felipe(CodeKind::Secret, 0)));
+        assert_eq!(legacy("NPEAZMYRT"), Err(CodeError::BadChecksum));
+        assert_eq!(legacy("NPEAZMYRS").unwrap().to_string(), "FELIPELA");
+        assert_eq!(Code::parse("NPEAZMYRS"), Err(CodeError::Legacy));
+        assert_eq!(Code::parse("NPEAZMYRT"), Err(CodeError::WrongLength(9)));
+    }
+
+    #[test]
+    fn codes_go_to_the_right_slot() {
+        let code = |player_id: &str, kind, idx| Code {
+            player_id: player_id.to_string(),
+            kind,
+            idx,
+        };
+        let mut discovered = vec![String::new(); NSECRETS];
+        let mut unlocked = vec![String::new(); NSECRETS];
+        let slot = |c: &Code, discovered: &[String], unlocked: &[String]| {
+            c.slot("PLAYER", discovered, unlocked)
+        };
+
+        let own_secret = code("PLAYER", CodeKind::Secret, 2);
+        let own_level = code("PLAYER", CodeKind::Level, 20);
+        let other_secret = code("OTHERS", CodeKind::Secret, 2);
+        let other_level = code("OTHERS", CodeKind::Level, 2);
+        let no_secret = code("PLAYER", CodeKind::Secret, NSECRETS);
+        assert_eq!(
+            slot(&own_secret, &discovered, &unlocked),
+            Ok(CodeSlot::Discovered(2))
+        );
+        assert_eq!(
+            slot(&own_level, &discovered, &unlocked),
+            Ok(CodeSlot::Level(20))
+        );
+        assert_eq!(
+            slot(&other_level, &discovered, &unlocked),
+            Err(CodeError::NotForThisPlayer)
+        );
+        assert_eq!(
+            slot(&no_secret, &discovered, &unlocked),
+            Err(CodeError::UnknownType)
+        );
+        assert_eq!(
+            slot(&other_secret, &discovered, &unlocked),
+            Err(CodeError::NotDiscovered)
+        );
+        discovered[2] = own_secret.encode();
+        assert_eq!(
+            slot(&other_secret, &discovered, &unlocked),
+            Ok(CodeSlot::Unlocked(2))
+        );
+        unlocked[2] = other_secret.encode();
+        assert_eq!(
+            slot(&other_secret, &discovered, &unlocked),
+            Err(CodeError::Duplicate)
+        );
+    }
+}
@@ -1,10 +1,6 @@
-use crate::animation::*;
-use crate::barrels::*;
 use crate::consts::*;
-use crate::game::*;
 use bevy::math::bounding::{Aabb2d, BoundingCircle, IntersectsVolume};
 use bevy::math::*;
-use bevy::prelude::*;
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Collision {
@@ -102,88 +98,3 @@ pub fn collision(body: Body, obst_pos: Vec2, obst_size: Vec2) -> Option<Collisio
     }
     Some(side)
 }
-
-pub fn collision_with_bricks(
-    commands: &mut Commands,
-    body: Body,
-    body_direction: Vec2,
-    bricks: &mut Query<&mut Brick>,
-    game: &mut Game,
-) -> Vec<Collision> {
-    let mut collisions: Vec<Collision> = Vec::new();
-    let body_pos = match body {
-        Body::Rectangular(pos, _) => pos,
-        Body::Round(pos, _) => pos,
-    };
-
-    // Check collisions with bricks in the vicinity of the ball
-    let (body_r, body_c) = game_xy_to_rc(body_pos.x, body_pos.y);
-    let min_row = if body_r > 0 { body_r - 1 } else { 0 };
-    let max_row = (body_r + 1).min(GRID_ROWS - 1);
-    let min_col = if body_c > 0 { body_c - 1 } else { 0 };
-    let max_col = (body_c + 1).min(GRID_COLS - 1);
-
-    for r in min_row..=max_row {
-        for c in min_col..=max_col {
-            // Get brick at position
-            let grid_idx: usize = game_rc_to_idx(r, c);
-
-            // Skip loop if no brick at this position
-            let Some(brick_entity) = game.grid[grid_idx] else {
-                continue;
-            };
-
-            // Check collision
-            let brick_pos: Vec2 = game_rc_to_xy(r, c).into();
-            let Some(side) = collision(body, brick_pos, BRICK_SIZE) else {
-                continue;
-            };
-
-            if (side == Collision::Left && body_direction.x <= 0.)
-                || (side == Collision::Right && body_direction.x >= 0.)
-                || (side == Collision::Bottom && body_direction.y <= 0.)
-                || (side == Collision::Top && body_direction.y >= 0.)
-            {
-                continue;
-            }
-            collisions.push(side);
-
-            let Ok(mut b) = bricks.get_mut(brick_entity) else {
-                continue;
-            };
-
-            commands.spawn((
-                Sprite::from_image(game.h_brick_frames[b.variant][0].clone()),
-                Transform::from_xyz(brick_pos.x, brick_pos.y, LAYER_EXPLOSIONS),
-                Animation {
-                    timer: Timer::from_seconds(1.0 / BRICK_FRAMERATE, TimerMode::Repeating),
-                    frozen: false,
-                    variant: AnimationType::Despawn,
-                    frames: game.h_brick_frames[b.variant].clone(),
-                    current_frame: 0,
-                    reverse: false,
-                    velocity: Vec2::ZERO,
-                },
-                GameTag,
-            ));
-
-            if b.variant < 12 || b.variant == 14 {
-                // Eliminate old brick
-                commands.entity(brick_entity).despawn();
-                game.grid[grid_idx] = None;
-                game.bricks_left -= 1;
-
-                // Extra life if all bricks destroyed
-                if game.bricks_left == 0 {
-                    game.nlives += 1;
-                }
-
-                // Randomly spawn barrels upon impact
-                barrels_spawn(commands, Vec3::new(brick_pos.x, brick_pos.y, 0.), game);
-            } else if b.variant == 12 {
-                b.variant = 11;
-            }
-        }
-    }
-    collisions
-}
@@ -1,10 +1,45 @@
 use bevy::prelude::*;
 use std::f32::consts::*;
 
-pub const SAVE_FILENAME: &str = ".merino_breakout.txt";
+pub const SAVE_VERSION: u32 = 1;
+// Folder of the profile saves, in CONFIG_DIRNAME of the user's data dir (e.g. ~/.local/share)
+pub const PROFILES_DIRNAME: &str = "profiles";
+// File in the data dir with the name of the last profile used
+pub const PROFILE_FILENAME: &str = "profile.txt";
+pub const DEFAULT_PROFILE: &str = "PLAYER";
+pub const PROFILE_NAME_LEN: usize = 10;
+// Folder in the data dir with the replay of the last level of each profile
+pub const REPLAYS_DIRNAME: &str = "replays";
+// Handles also fill the name of new high scores
+pub const PLAYER_HANDLE_LEN: usize = HIGHSCORE_NAME_LEN;
+// Save of older versions in the home folder, whose codes go to the default profile
+pub const LEGACY_SAVE_FILENAME: &str = ".merino_breakout.txt";
+pub const HIGHSCORES_LEN: usize = 10;
+pub const HIGHSCORE_NAME_LEN: usize = 10;
+// Folder in the user's config and data dirs (e.g. ~/.config)
+pub const CONFIG_DIRNAME: &str = "merino_breakout";
+pub const CONTROLS_FILENAME: &str = "controls.ron";
+// Actions listed at once in the controls screen, which scrolls to the highlighted one
+pub const CONTROLS_ROWS: usize = 18;
+pub const OPTIONS_VOLUME_STEP: f32 = 0.1;
 
-pub const NLEVELS: usize = 8;
+// Copies of a sound that can play at once, and seconds between two starts of it
+pub const AUDIO_MAX_VOICES: usize = 4;
+pub const AUDIO_COOLDOWN_SECS: f32 = 0.04;
+// Panned sounds are heard from this far in front of the game area (a quarter of its
+// width keeps them about as loud as the rest), and made louder to make up for the distance
+pub const AUDIO_PAN_DEPTH: f32 = GAMEAREA_WIDTH / 4.;
+pub const AUDIO_PAN_GAIN: f32 = 1.6;
+// Playback speed of ball impacts, from the initial to the maximum ball speed
+pub const AUDIO_HIT_PITCH: [f32; 2] = [0.9, 1.2];
+
+pub const CAMPAIGN_FILE: &str = "levels/main.campaign.ron";
+// Codes identify levels with a single letter
+pub const MAX_LEVELS: usize = 26;
+pub const NSECRETS: usize = 8;
 pub const NLIVES: u32 = 3;
+// Default simulation steps per second (changed with -t)
+pub const SIM_TICK_HZ: f64 = 120.;
 
 pub const SCREEN_WIDTH: f32 = 800.;
 pub const SCREEN_HEIGHT: f32 = 640.;
@@ -26,7 +61,21 @@ pub const INFOAREA_HEADER_Y: f32 = 150.;
 pub const INFOAREA_MSG_Y: f32 = -250.;
 pub const INFOAREA_TIMER_Y: f32 = 75.;
 pub const INFOAREA_LIVES_Y: f32 = 0.;
+pub const INFOAREA_SCORE_Y: f32 = -30.;
+pub const INFOAREA_SEED_Y: f32 = -60.;
+pub const INFOAREA_EFFECTS_Y: f32 = -100.;
+pub const INFOAREA_EFFECTS_DY: f32 = 26.;
 pub const INFOAREA_TIMER: f32 = 3.;
+// Life icons, in rows centered on INFOAREA_LIVES_Y. Lives beyond the icons are
+// counted in the slot after the last one
+pub const LIVES_ICONS_MAX: usize = 9;
+pub const LIVES_ICONS_PER_ROW: usize = 5;
+pub const LIVES_ICON_DX: f32 = 30.;
+pub const LIVES_ICON_DY: f32 = 16.;
+pub const LIVES_ICON_SCALE: f32 = 0.6;
+pub const LIVES_BLINK_FRAMES: usize = 8;
+pub const LIVES_BLINK_FRAMERATE: f32 = 10.;
+pub const LIVES_FALL_SPEED: f32 = 40.;
 
 pub const GRID_ROWS: usize = 22;
 pub const GRID_COLS: usize = 15;
@@ -38,10 +87,23 @@ pub const BRICK_TYPES: usize = 15;
 pub const BRICK_FRAMES: usize = 4;
 pub const IBRICK_FRAMES: usize = 4;
 pub const BRICK_FRAMERATE: f32 = 20.0;
+// Points for hitting each brick variant. The first hit on a 2-hit brick (c)
+// scores on its own, the second scores as a 1-hit brick (b)
+pub const BRICK_POINTS: [u32; BRICK_TYPES] = [
+    50, 60, 70, 80, 90, 100, 110, 120, 130, 140, 50, 80, 150, 0, 200,
+];
+pub const COMBO_MAX: u32 = 8;
+pub const MEANIE_POINTS: u32 = 500;
+pub const TIME_BONUS_POINTS: u32 = 100;
 
 pub const PORTAL_FRAMES: usize = 4;
 pub const PORTAL_FRAMERATE: f32 = 20.0;
 
+pub const GAMEOVER_FRAMES: usize = 15;
+pub const GAMEOVER_FRAMERATE: f32 = 15.0;
+pub const START_FRAMES: usize = 13;
+pub const START_FRAMERATE: f32 = 10.0;
+
 pub const BARREL_TITLES: [&str; BARREL_TYPES] = [
     "Extended!",   // Brown
     "Gun!",        // Red
@@ -60,7 +122,7 @@ pub const BARREL_FRAMES: usize = 10;
 pub const BARREL_SPEED: f32 = 150.;
 pub const BARREL_CHANCE: f64 = 0.15;
 pub const BARREL_SIZE: Vec2 = Vec2::new(40., 18.);
-pub const BARREL_WEIGHTS: [u32; BARREL_TYPES] = [4, 0, 2, 0, 0, 2, 2, 0, 0, 0];
+pub const BARREL_WEIGHTS: [u32; BARREL_TYPES] = [4, 0, 2, 0, 0, 2, 2, 0, 1, 0];
 
 pub const LAYER_BG: f32 = 0.;
 pub const LAYER_PORTAL_BG: f32 = 1.;
@@ -74,6 +136,7 @@ pub const LAYER_GUN: f32 = 5.;
 pub const LAYER_EXPLOSIONS: f32 = 6.;
 pub const LAYER_PORTAL_FG: f32 = 6.;
 pub const LAYER_BANNER: f32 = 7.;
+pub const LAYER_OVERLAY: f32 = 8.;
 
 pub const SHADOW_DX: f32 = 10.;
 pub const SHADOW_DY: f32 = -10.;
@@ -101,6 +164,7 @@ pub const PADDLE_MIN_ANGLE: f32 = 0.5 * FRAC_PI_8;
 pub const PADDLE_MAX_ANGLE: f32 = PI - PADDLE_MIN_ANGLE;
 pub const PADDLE_MIN_SPEED: f32 = 400.0;
 pub const PADDLE_MAX_SPEED: f32 = 800.0;
+pub const GAMEPAD_DEADZONE: f32 = 0.15;
 
 pub const MEANIES_TYPES: usize = 3;
 pub const MEANIES_FRAMERATE: f32 = 8.;
@@ -109,6 +173,9 @@ pub const MEANIES_SPEED: f32 = 40.;
 pub const MEANIES_NFRAMES: [usize; MEANIES_TYPES] = [8, 8, 10];
 pub const MEANIES_PORTAL_X: [f32; 2] = [GAMEAREA_CENTER_X - 165., GAMEAREA_CENTER_X + 165.];
 pub const MEANIES_PORTAL_Y: f32 = 300.;
+pub const MEANIES_PORTAL_FRAMES: usize = 4;
+pub const MEANIES_PORTAL_DOOR_Y: f32 = GAMEAREA_MAXY - 75.;
+pub const MEANIES_PORTAL_DOOR_SIZE: Vec2 = Vec2::new(90., 70.);
 pub const MEANIES_MIN_ANGLE: f32 = -PI - FRAC_PI_8;
 pub const MEANIES_MAX_ANGLE: f32 = FRAC_PI_8;
 pub const MEANIES_MINY: f32 = GAMEAREA_MINY + 2. * BRICK_HEIGHT;
@@ -179,10 +246,28 @@ pub const BULLET_SIZE: Vec2 = Vec2::new(7., 20.);
 
 pub const TRANSITION_BANNER_SECS: u64 = 4;
 
+// Letters identifying the player in the codes
+pub const PLAYER_ID_LEN: usize = 6;
+// Codes start with their format version. The payload holds the player ID,
+// kind and index, followed by a keyed MAC and a check character
+pub const CODE_VERSION: char = '2';
+pub const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+pub const CODE_PAYLOAD_LEN: usize = 7;
+// 40 bits, so guessing a code for another player takes about 10^12 tries
+pub const CODE_MAC_LEN: usize = 8;
+pub const CODE_IDX_BITS: u32 = 5;
+pub const CODE_LEN: usize = 1 + CODE_PAYLOAD_LEN + CODE_MAC_LEN + 1;
+// The MAC key comes from MERINO_CODE_KEY at build time, see build.rs
+pub const CODE_KEY_CONTEXT: &str = "merino-breakout 2025 code mac v2";
+// DEVELOPMENT ONLY: used when MERINO_CODE_KEY is not set. It is public,
+// so anyone can forge the codes of a build made with it
+pub const CODE_DEV_KEY: &str = "merino-breakout development key";
+// Vigenere codes of older versions
 pub const CIPHER_KEY: &str = "RUMPLESTILTSKIN";
-pub const USERNAME_LEN: usize = 6;
-pub const CODE_LEN: usize = USERNAME_LEN + 3;
-pub const SECRETS: [&str; NLEVELS] = [
+pub const LEGACY_CODE_LEN: usize = PLAYER_ID_LEN + 3;
+// Characters picked with the gamepad in the shop
+pub const SHOP_PAD_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+pub const SECRETS: [&str; NSECRETS] = [
     "Multiball",
     "The Magnet",
     "Portal to Hell",
@@ -214,31 +299,9 @@ pub const HINTS: [&str; 8] = [
     "You get an extra life when\nyou destroy all bricks in a level",
 ];
 
-pub const LEVEL_TITLES: [&str; NLEVELS] = [
-    "Angry Peccy",
-    "Free Bananas",
-    "Mochinuts",
-    "Hell\n(crank sound up to 11 for this one)",
-    "Lipu",
-    "The Toxic Cloud of Capitalism",
-    "Tensors... so complex",
-    "PRISM-S Invisible Bug",
-];
-
-pub const LEVEL_COLORS: [Srgba; NLEVELS] = [
-    Srgba::new(1.0, 0.1, 0.1, 1.0), // Evil Peccy
-    Srgba::new(1.0, 0.9, 0.2, 1.0), // Banana
-    Srgba::new(1.0, 0.0, 0.5, 1.0), // Mochinuts
-    Srgba::new(0.0, 0.5, 0.5, 1.0), // Hell
-    Srgba::new(0.9, 0.6, 0.1, 1.0), // Lipu
-    Srgba::new(1.0, 1.0, 1.0, 1.0), // Toxic Cloud
-    Srgba::new(1.0, 0.0, 0.0, 1.0), // Tensors...
-    Srgba::new(0.2, 0.2, 0.2, 1.0), // Invisible Bug
-];
-
-pub const LEVEL_TIMERS: [f32; NLEVELS] = [210., 150., 150., 150., 150., 150., 150., 150.];
 pub const UNLOCKED_PORTAL_TIMER: f32 = 10.;
 
+// Brick variants in the level grids (assets/levels/*.level.ron)
 // 0: Yellow
 // 1: Green
 // 2: Blue
@@ -255,201 +318,6 @@ pub const UNLOCKED_PORTAL_TIMER: f32 = 10.;
 // d: Gold (indestructible)
 // e: Invisible
 
-pub const LEVELS: [[&[u8; GRID_COLS]; GRID_ROWS]; NLEVELS] = [
-    [
-        b"               ",
-        b"               ",
-        b"               ",
-        b"    0000000    ",
-        b"   000000000   ",
-        b"   0bbb0bbb0   ",
-        b"   003000300   ",
-        b"   000000000   ",
-        b"   000000000   ",
-        b"   00     00   ",
-        b"   0 00000 0   ",
-        b"  00000000000  ",
-        b"  00000000000  ",
-        b"  00000000000  ",
-        b"               ",
-        b"  99999999999  ",
-        b"               ",
-        b"   000   000   ",
-        b"   000   000   ",
-        b"   000   000   ",
-        b"   000   000   ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"               ",
-        b"  5            ",
-        b"  0        00  ",
-        b"  000     000  ",
-        b"   000000000   ",
-        b"    0000000    ",
-        b"  5   0000     ",
-        b"  0        00  ",
-        b"  000     000  ",
-        b"   000000000   ",
-        b"    0000000    ",
-        b"  5   0000     ",
-        b"  0        00  ",
-        b"  000     000  ",
-        b"   000000000   ",
-        b"    0000000    ",
-        b"      0000     ",
-        b"               ",
-        b"               ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"  222     333  ",
-        b" 22 22   33 33 ",
-        b"2     2 3     3",
-        b"2     2 3     3",
-        b"2     2 3     3",
-        b" 22 22   33 33 ",
-        b"  222     333  ",
-        b"               ",
-        b"               ",
-        b"               ",
-        b"  444     111  ",
-        b" 44 44   11 11 ",
-        b"4     4 1     1",
-        b"d     d d     d",
-        b"d     d d     d",
-        b" dd dd   dd dd ",
-        b"  ddd     ddd  ",
-        b"               ",
-        b"               ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"        11     ",
-        b"        11     ",
-        b"       71      ",
-        b"  71   71      ",
-        b"  771  71  771 ",
-        b"   771 71 771  ",
-        b"    77171771   ",
-        b" 1   77 777    ",
-        b" 11111   7777  ",
-        b" 11111   11111 ",
-        b"  77777 711111 ",
-        b"     17177   1 ",
-        b"    1771777    ",
-        b"   177 17177   ",
-        b"  177  17 177  ",
-        b"       17  17  ",
-        b"      11       ",
-        b"      11       ",
-        b"               ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"   b bbbbb b   ",
-        b"  b b     b b  ",
-        b" b           b ",
-        b" b0         0b ",
-        b" b0 000 000 0b ",
-        b" b000b0 0b000b ",
-        b" b0b0b0 0b0b0b ",
-        b" b0b       b0b ",
-        b" b0b       b0b ",
-        b" bbb  bbb  bbb ",
-        b"   b  bbb  b   ",
-        b"    b  b  b    ",
-        b"    b  b  b    ",
-        b"     bbbbb     ",
-        b"      3 3      ",
-        b"      3 3      ",
-        b"      3 3      ",
-        b"       3       ",
-        b"               ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"               ",
-        b"               ",
-        b"    ccccccc    ",
-        b"  ccbbbbbbbcc  ",
-        b" cbbbbbbbbbbbc ",
-        b" c   bbbbb   c ",
-        b" c   bbbbb   c ",
-        b" cbbbbbbbbbbbc ",
-        b" cbbbb   bbbbc ",
-        b"  cbb     bbc  ",
-        b"  ccbbbbbbbcc  ",
-        b"    ccccccc    ",
-        b"               ",
-        b"   d       d   ",
-        b"   ddddddddd   ",
-        b"    ddddddd    ",
-        b"               ",
-        b"               ",
-        b"               ",
-        b"               ",
-    ],
-    [
-        b"               ",
-        b" 77  1         ",
-        b"  77 4         ",
-        b" 17714         ",
-        b"3  77 40       ",
-        b"  477  2       ",
-        b"   177 4       ",
-        b"  4 77 4       ",
-        b"    077  1     ",
-        b"  24 77 4      ",
-        b"   4 1770      ",
-        b"    4 77 12    ",
-        b"   3  077 4    ",
-        b"     1 77 2    ",
-        b"      4 7731   ",
-        b"     2  77 4   ",
-        b"       4 7701  ",
-        b"        177 4  ",
-        b"      2  0772  ",
-        b"        4 77 1 ",
-        b"         3 77  ",
-        b"        4 1770 ",
-    ],
-    [
-        b"               ",
-        b"               ",
-        b"               ",
-        b"   e       e   ",
-        b"  e e     e e  ",
-        b"  e e     e e  ",
-        b"  e  d   d  e  ",
-        b"  e   d d   e  ",
-        b"   eed   dee   ",
-        b"  e  d e d  e  ",
-        b"  e         e  ",
-        b"   e  ddd  e   ",
-        b"  e  d   d  e  ",
-        b" e  e     e  e ",
-        b" e  e     e  e ",
-        b" e  e     e  e ",
-        b" e  e     e  e ",
-        b"  e  e   e  e  ",
-        b"   e e   e e   ",
-        b"    ee   ee    ",
-        b"               ",
-        b"               ",
-    ],
-];
-
 pub const CREDITS: &str = "CREDITS\n\
 			   \n\
 			   Code and levels in the public domain\n\
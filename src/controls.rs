@@ -0,0 +1,355 @@
+use crate::consts::*;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// Things the player can do, bound to keys and gamepad buttons
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Boost,
+    Fire,
+    Pause,
+    Confirm,
+    Back,
+    Up,
+    Down,
+    Delete,
+    Yes,
+    No,
+    Play,
+    Shop,
+    Reset,
+    Credits,
+    HighScores,
+    Editor,
+    Controls,
+    Options,
+    Profiles,
+    Playtest,
+    Save,
+    PrevLevel,
+    NextLevel,
+    PrevBrick,
+    NextBrick,
+    Paint,
+    Erase,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Screens where actions are read. Keys and buttons must be unique within each
+pub enum ActionContext {
+    /// Playing a level
+    Level,
+    /// Menus, shop and dialogs
+    Menus,
+    /// Level editor
+    Editor,
+}
+
+pub const ACTIONS: [Action; 29] = [
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Boost,
+    Action::Fire,
+    Action::Pause,
+    Action::Confirm,
+    Action::Back,
+    Action::Up,
+    Action::Down,
+    Action::Delete,
+    Action::Yes,
+    Action::No,
+    Action::Play,
+    Action::Shop,
+    Action::Reset,
+    Action::Credits,
+    Action::HighScores,
+    Action::Editor,
+    Action::Controls,
+    Action::Options,
+    Action::Profiles,
+    Action::Playtest,
+    Action::Save,
+    Action::PrevLevel,
+    Action::NextLevel,
+    Action::PrevBrick,
+    Action::NextBrick,
+    Action::Paint,
+    Action::Erase,
+];
+
+/// Keys that pick a brush in the level editor, in brick variant order (0-9, a-e).
+/// They are not actions, so actions read in the editor can not be bound to them
+pub const BRUSH_KEYS: [KeyCode; BRICK_TYPES] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+];
+
+impl Action {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "MOVE LEFT",
+            Action::MoveRight => "MOVE RIGHT",
+            Action::Boost => "MOVE FASTER",
+            Action::Fire => "RELEASE / FIRE",
+            Action::Pause => "PAUSE",
+            Action::Confirm => "CONFIRM",
+            Action::Back => "BACK",
+            Action::Up => "UP",
+            Action::Down => "DOWN",
+            Action::Delete => "DELETE",
+            Action::Yes => "YES",
+            Action::No => "NO",
+            Action::Play => "MENU: PLAY",
+            Action::Shop => "MENU: SHOP",
+            Action::Reset => "MENU: RESET PROGRESS",
+            Action::Credits => "MENU: CREDITS",
+            Action::HighScores => "MENU: HIGH SCORES",
+            Action::Editor => "MENU: LEVEL EDITOR",
+            Action::Controls => "MENU: CONTROLS",
+            Action::Options => "MENU: OPTIONS",
+            Action::Profiles => "MENU: PROFILES",
+            Action::Playtest => "EDITOR: PLAY-TEST",
+            Action::Save => "EDITOR: SAVE",
+            Action::PrevLevel => "EDITOR: PREVIOUS LEVEL",
+            Action::NextLevel => "EDITOR: NEXT LEVEL",
+            Action::PrevBrick => "EDITOR: PREVIOUS BRICK",
+            Action::NextBrick => "EDITOR: NEXT BRICK",
+            Action::Paint => "EDITOR: PAINT",
+            Action::Erase => "EDITOR: ERASE",
+        }
+    }
+
+    fn contexts(&self) -> &'static [ActionContext] {
+        use ActionContext as C;
+        match self {
+            // Also move the editor cursor
+            Action::MoveLeft | Action::MoveRight => &[C::Level, C::Menus, C::Editor],
+            Action::Boost | Action::Fire | Action::Pause => &[C::Level],
+            Action::Up | Action::Down | Action::Back => &[C::Menus, C::Editor],
+            Action::Playtest
+            | Action::Save
+            | Action::PrevLevel
+            | Action::NextLevel
+            | Action::PrevBrick
+            | Action::NextBrick
+            | Action::Paint
+            | Action::Erase => &[C::Editor],
+            _ => &[C::Menus],
+        }
+    }
+
+    /// Whether both actions can be read on the same screen
+    fn shares_context(&self, other: Action) -> bool {
+        self.contexts()
+            .iter()
+            .any(|context| other.contexts().contains(context))
+    }
+
+    fn default_binding(&self) -> Binding {
+        use GamepadButton as B;
+        let (keys, buttons): (&[KeyCode], &[GamepadButton]) = match self {
+            Action::MoveLeft => (&[KeyCode::ArrowLeft], &[B::DPadLeft]),
+            Action::MoveRight => (&[KeyCode::ArrowRight], &[B::DPadRight]),
+            Action::Boost => (
+                &[KeyCode::ShiftLeft],
+                &[
+                    B::LeftTrigger,
+                    B::RightTrigger,
+                    B::LeftTrigger2,
+                    B::RightTrigger2,
+                ],
+            ),
+            Action::Fire => (&[KeyCode::Space], &[B::South, B::East, B::West, B::North]),
+            Action::Pause => (&[KeyCode::KeyP, KeyCode::Escape], &[B::Start]),
+            Action::Confirm => (&[KeyCode::Enter], &[B::South, B::Start]),
+            Action::Back => (&[KeyCode::Escape], &[B::East, B::Select]),
+            Action::Up => (&[KeyCode::ArrowUp], &[B::DPadUp]),
+            Action::Down => (&[KeyCode::ArrowDown], &[B::DPadDown]),
+            Action::Delete => (&[KeyCode::Backspace], &[B::West]),
+            Action::Yes => (&[KeyCode::KeyY], &[]),
+            Action::No => (&[KeyCode::KeyN], &[]),
+            Action::Play => (&[KeyCode::Digit1], &[]),
+            Action::Shop => (&[KeyCode::Digit2], &[]),
+            Action::Reset => (&[KeyCode::Digit3], &[]),
+            Action::Credits => (&[KeyCode::Digit4], &[]),
+            Action::HighScores => (&[KeyCode::KeyH], &[]),
+            Action::Editor => (&[KeyCode::KeyE], &[]),
+            Action::Controls => (&[KeyCode::KeyC], &[]),
+            Action::Options => (&[KeyCode::KeyO], &[]),
+            Action::Profiles => (&[KeyCode::KeyU], &[]),
+            Action::Playtest => (&[KeyCode::KeyP], &[B::Start]),
+            Action::Save => (&[KeyCode::KeyS], &[B::North]),
+            Action::PrevLevel => (&[KeyCode::PageUp], &[B::LeftTrigger]),
+            Action::NextLevel => (&[KeyCode::PageDown], &[B::RightTrigger]),
+            Action::PrevBrick => (&[KeyCode::Comma], &[B::LeftTrigger2]),
+            Action::NextBrick => (&[KeyCode::Period], &[B::RightTrigger2]),
+            Action::Paint => (&[KeyCode::Space], &[B::South]),
+            Action::Erase => (&[KeyCode::Delete, KeyCode::Backspace], &[B::West]),
+        };
+        Binding {
+            keys: keys.to_vec(),
+            buttons: buttons.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+/// Keys and gamepad buttons that trigger an action
+pub struct Binding {
+    #[serde(default)]
+    pub keys: Vec<KeyCode>,
+    #[serde(default)]
+    pub buttons: Vec<GamepadButton>,
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+/// Bindings of every action, stored in the user's config folder
+pub struct Controls {
+    bindings: BTreeMap<Action, Binding>,
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            bindings: ACTIONS
+                .iter()
+                .map(|action| (*action, action.default_binding()))
+                .collect(),
+        }
+    }
+}
+
+impl Controls {
+    /// Reads the bindings from the config file. Actions missing from the file keep their defaults
+    pub fn load() -> Self {
+        let mut controls = Controls::default();
+        let path = config_dir()
+            .unwrap_or_default()
+            .join(CONFIG_DIRNAME)
+            .join(CONTROLS_FILENAME);
+        let Ok(bytes) = fs::read(&path) else {
+            return controls;
+        };
+        match ron::de::from_bytes::<Controls>(&bytes) {
+            Ok(file) => controls.bindings.extend(file.bindings),
+            Err(err) => error!("Could not parse {}: {err}", path.display()),
+        }
+        controls
+    }
+
+    pub fn save(&self) {
+        let dir = config_dir().unwrap_or_default().join(CONFIG_DIRNAME);
+        let Ok(text) = ron::ser::to_string_pretty(self, default()) else {
+            return;
+        };
+        if let Err(err) =
+            fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(CONTROLS_FILENAME), text))
+        {
+            error!("Could not write {CONTROLS_FILENAME}: {err}");
+        }
+    }
+
+    pub fn binding(&self, action: Action) -> &Binding {
+        &self.bindings[&action]
+    }
+
+    /// Makes `key` the only key of an action, keeping its gamepad buttons.
+    /// Fails with the title of what already uses the key on the same screens
+    pub fn rebind(&mut self, action: Action, key: KeyCode) -> Result<(), &'static str> {
+        if action.contexts().contains(&ActionContext::Editor) && BRUSH_KEYS.contains(&key) {
+            return Err("EDITOR: BRUSH");
+        }
+        if let Some((other, _)) = self.bindings.iter().find(|(other, binding)| {
+            **other != action && other.shares_context(action) && binding.keys.contains(&key)
+        }) {
+            return Err(other.title());
+        }
+        if let Some(binding) = self.bindings.get_mut(&action) {
+            binding.keys = vec![key];
+        }
+        Ok(())
+    }
+
+    pub fn pressed(
+        &self,
+        action: Action,
+        keys: &ButtonInput<KeyCode>,
+        gamepads: &Query<&Gamepad>,
+    ) -> bool {
+        let binding = self.binding(action);
+        keys.any_pressed(binding.keys.iter().copied())
+            || gamepads
+                .iter()
+                .any(|gamepad| gamepad.any_pressed(binding.buttons.iter().copied()))
+    }
+
+    pub fn just_pressed(
+        &self,
+        action: Action,
+        keys: &ButtonInput<KeyCode>,
+        gamepads: &Query<&Gamepad>,
+    ) -> bool {
+        let binding = self.binding(action);
+        keys.any_just_pressed(binding.keys.iter().copied())
+            || gamepads
+                .iter()
+                .any(|gamepad| gamepad.any_just_pressed(binding.buttons.iter().copied()))
+    }
+
+    /// Name of the first key of an action, to show in menus
+    pub fn key_name(&self, action: Action) -> String {
+        self.binding(action)
+            .keys
+            .first()
+            .map_or("-".to_string(), |key| key_name(*key))
+    }
+}
+
+/// Short name of a key (e.g. "1" for Digit1, "A" for KeyA)
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    let short = name
+        .strip_prefix("Digit")
+        .or_else(|| name.strip_prefix("Key"))
+        .unwrap_or(&name);
+    short.to_uppercase()
+}
+
+#[derive(SystemParam)]
+/// Player actions from the keyboard and gamepads, as bound in `Controls`
+pub struct Actions<'w, 's> {
+    pub controls: Res<'w, Controls>,
+    pub keys: Res<'w, ButtonInput<KeyCode>>,
+    pub gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl Actions<'_, '_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.controls.pressed(action, &self.keys, &self.gamepads)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.controls
+            .just_pressed(action, &self.keys, &self.gamepads)
+    }
+}
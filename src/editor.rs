@@ -0,0 +1,373 @@
+use crate::collisions::*;
+use crate::consts::*;
+use crate::controls::*;
+use crate::game::*;
+use crate::level::*;
+
+use super::{GameState, despawn_all};
+use bevy::asset::io::file::FileAssetReader;
+use bevy::input::mouse::AccumulatedMouseScroll;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use std::collections::HashSet;
+
+#[derive(Component)]
+struct EditorTag;
+
+#[derive(Component)]
+struct EditorBrick;
+
+#[derive(Component)]
+struct EditorCursor;
+
+#[derive(Component)]
+struct EditorBrush;
+
+#[derive(Component)]
+struct EditorBackground;
+
+#[derive(Component)]
+struct EditorInfo;
+
+#[derive(Resource, Default)]
+/// Editor state, kept while play-testing so the editor resumes where it was left
+pub struct Editor {
+    /// Level being edited
+    level: usize,
+    /// Grid row and column under the cursor
+    cursor: (usize, usize),
+    /// Brick variant painted by the brush
+    brush: usize,
+    /// Levels with changes that have not been saved to disk
+    unsaved: HashSet<usize>,
+    /// Result of the last save, shown in the info area
+    status: String,
+    /// Whether the bricks must be spawned again
+    dirty: bool,
+}
+
+pub fn editor_plugin(app: &mut App) {
+    app.init_resource::<Editor>()
+        .add_systems(OnEnter(GameState::Editor), editor_enter)
+        .add_systems(OnExit(GameState::Editor), despawn_all::<EditorTag>)
+        .add_systems(
+            Update,
+            (editor_input, editor_update)
+                .chain()
+                .run_if(in_state(GameState::Editor)),
+        );
+}
+
+fn get_editor_help(controls: &Controls) -> String {
+    let key = |action| controls.key_name(action);
+    format!(
+        "{}/{}/{}/{}/Mouse: Move\n\
+         {}/Click: Paint\n\
+         {}/Right Click: Erase\n\
+         0-9 A-E/{}/{}/Wheel: Brick\n\
+         {}/{}: Level\n\
+         {}: Play-test\n\
+         {}: Save\n\
+         {}: Menu",
+        key(Action::Up),
+        key(Action::Down),
+        key(Action::MoveLeft),
+        key(Action::MoveRight),
+        key(Action::Paint),
+        key(Action::Erase),
+        key(Action::PrevBrick),
+        key(Action::NextBrick),
+        key(Action::PrevLevel),
+        key(Action::NextLevel),
+        key(Action::Playtest),
+        key(Action::Save),
+        key(Action::Back),
+    )
+}
+
+fn editor_enter(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    mut game: ResMut<Game>,
+    controls: Res<Controls>,
+) {
+    game.playtest = false;
+    editor.level = editor.level.min(game.nlevels.saturating_sub(1));
+    editor.dirty = true;
+
+    commands.spawn((
+        Sprite::default(),
+        Transform::from_xyz(GAMEAREA_CENTER_X, GAMEAREA_CENTER_Y, LAYER_BG),
+        EditorBackground,
+        EditorTag,
+    ));
+    commands.spawn((
+        Sprite::from_color(Color::srgba(1.0, 1.0, 1.0, 0.4), BRICK_SIZE),
+        Transform::from_xyz(0., 0., LAYER_BANNER),
+        EditorCursor,
+        EditorTag,
+    ));
+    commands.spawn((
+        Sprite::from_image(game.h_logo.clone()),
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_LOGO_Y, LAYER_BANNER),
+        EditorTag,
+    ));
+    commands.spawn((
+        editor_brick_sprite(&game, editor.brush),
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_LIVES_Y, LAYER_BANNER),
+        EditorBrush,
+        EditorTag,
+    ));
+    commands.spawn((
+        Text2d::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_HEADER_Y, LAYER_BANNER),
+        TextLayout::new_with_justify(Justify::Center),
+        EditorInfo,
+        EditorTag,
+    ));
+    commands.spawn((
+        Text2d::new(get_editor_help(&controls)),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_MSG_Y + 50., LAYER_BANNER),
+        TextLayout::new_with_justify(Justify::Center),
+        EditorTag,
+    ));
+}
+
+/// Invisible bricks (variant 14) are shown as translucent grey bricks in the editor
+fn editor_brick_sprite(game: &Game, variant: usize) -> Sprite {
+    if variant == 14 {
+        let mut sprite = Sprite::from_image(game.h_bricks[10].clone());
+        sprite.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
+        sprite
+    } else {
+        Sprite::from_image(game.h_bricks[variant].clone())
+    }
+}
+
+/// Converts game world coordinates to a grid row/column, if inside the grid
+fn editor_xy_to_rc(pos: Vec2) -> Option<(usize, usize)> {
+    let r = ((BALLAREA_MAXY - pos.y) / BRICK_HEIGHT).floor();
+    let c = ((pos.x - BALLAREA_MINX) / BRICK_WIDTH).floor();
+    if r < 0. || c < 0. || r >= GRID_ROWS as f32 || c >= GRID_COLS as f32 {
+        return None;
+    }
+    Some((r as usize, c as usize))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn editor_input(
+    mut editor: ResMut<Editor>,
+    mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    mut game_state: ResMut<NextState<GameState>>,
+    actions: Actions,
+    mouse: Res<ButtonInput<MouseButton>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera_comp: Single<(&Camera, &GlobalTransform)>,
+    asset_server: Res<AssetServer>,
+    campaigns: Res<Assets<Campaign>>,
+    mut levels: ResMut<Assets<Level>>,
+) {
+    let Some(campaign) = campaigns.get(&game.campaign) else {
+        return;
+    };
+    let Some(level_handle) = campaign.levels.get(editor.level) else {
+        return;
+    };
+
+    // Leave editor, reading again the levels whose changes were not saved
+    if actions.just_pressed(Action::Back) {
+        for level in editor.unsaved.drain() {
+            if let Some(path) = campaign.levels.get(level).and_then(|h| h.path()) {
+                asset_server.reload(path.clone());
+            }
+        }
+        game_state.set(GameState::Menu);
+        return;
+    }
+
+    // Play-test the level as it is now
+    if actions.just_pressed(Action::Playtest) {
+        game.current_level = editor.level;
+        game.nlives = NLIVES;
+        game.score = 0;
+        game.playtest = true;
+        rng.reset();
+        game_state.set(GameState::Game);
+        return;
+    }
+
+    // Save level back to its file
+    if actions.just_pressed(Action::Save)
+        && let (Some(level), Some(path)) = (levels.get(level_handle), level_handle.path())
+    {
+        let filepath = FileAssetReader::get_base_path()
+            .join("assets")
+            .join(path.path());
+        let saved = ron::ser::to_string_pretty(&level.to_file(), default())
+            .map_err(|e| e.to_string())
+            .and_then(|ron| std::fs::write(&filepath, ron).map_err(|e| e.to_string()));
+        editor.status = match saved {
+            Ok(()) => {
+                let level = editor.level;
+                editor.unsaved.remove(&level);
+                "Saved".to_string()
+            }
+            Err(e) => {
+                error!("Could not save {}: {e}", filepath.display());
+                "Save failed".to_string()
+            }
+        };
+    }
+
+    // Change level
+    if actions.just_pressed(Action::PrevLevel) && editor.level > 0 {
+        editor.level -= 1;
+        editor.status.clear();
+        editor.dirty = true;
+    }
+    if actions.just_pressed(Action::NextLevel) && editor.level + 1 < game.nlevels {
+        editor.level += 1;
+        editor.status.clear();
+        editor.dirty = true;
+    }
+
+    // Select brush, directly with its key
+    let keys = &actions.keys;
+    if let Some(brush) = BRUSH_KEYS.iter().position(|k| keys.just_pressed(*k)) {
+        editor.brush = brush;
+    }
+    if actions.just_pressed(Action::NextBrick) || scroll.delta.y > 0. {
+        editor.brush = (editor.brush + 1) % BRICK_TYPES;
+    } else if actions.just_pressed(Action::PrevBrick) || scroll.delta.y < 0. {
+        editor.brush = (editor.brush + BRICK_TYPES - 1) % BRICK_TYPES;
+    }
+
+    // Move cursor with the keyboard or gamepad...
+    let (mut r, mut c) = editor.cursor;
+    if actions.just_pressed(Action::Up) {
+        r = r.saturating_sub(1);
+    }
+    if actions.just_pressed(Action::Down) {
+        r = (r + 1).min(GRID_ROWS - 1);
+    }
+    if actions.just_pressed(Action::MoveLeft) {
+        c = c.saturating_sub(1);
+    }
+    if actions.just_pressed(Action::MoveRight) {
+        c = (c + 1).min(GRID_COLS - 1);
+    }
+    editor.cursor = (r, c);
+
+    // ... or with the mouse
+    let (camera, camera_tr) = camera_comp.into_inner();
+    let mouse_rc = window
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world_2d(camera_tr, pos).ok())
+        .and_then(editor_xy_to_rc);
+    if let Some(rc) = mouse_rc {
+        editor.cursor = rc;
+    }
+
+    // Paint or erase the brick under the cursor
+    let mut paint: Option<Option<usize>> = None;
+    if actions.pressed(Action::Paint) || (mouse_rc.is_some() && mouse.pressed(MouseButton::Left)) {
+        paint = Some(Some(editor.brush));
+    } else if actions.pressed(Action::Erase)
+        || (mouse_rc.is_some() && mouse.pressed(MouseButton::Right))
+    {
+        paint = Some(None);
+    }
+    let Some(brick) = paint else {
+        return;
+    };
+    let (r, c) = editor.cursor;
+    let grid_idx = r * GRID_COLS + c;
+    if levels.get(level_handle).map(|l| l.bricks[grid_idx]) != Some(brick) {
+        if let Some(level) = levels.get_mut(level_handle) {
+            level.bricks[grid_idx] = brick;
+        }
+        let level = editor.level;
+        editor.unsaved.insert(level);
+        editor.status.clear();
+        editor.dirty = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn editor_update(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    game: Res<Game>,
+    bricks: Query<Entity, With<EditorBrick>>,
+    cursor: Single<&mut Transform, With<EditorCursor>>,
+    brush: Single<&mut Sprite, (With<EditorBrush>, Without<EditorBackground>)>,
+    background: Single<&mut Sprite, With<EditorBackground>>,
+    info: Single<&mut Text2d, With<EditorInfo>>,
+    campaigns: Res<Assets<Campaign>>,
+    levels: Res<Assets<Level>>,
+) {
+    let Some(level) = campaign_level(&game.campaign, editor.level, &campaigns, &levels) else {
+        return;
+    };
+
+    // Cursor and brush
+    let (r, c) = editor.cursor;
+    let (x, y) = game_rc_to_xy(r, c);
+    cursor.into_inner().translation = Vec3::new(x, y, LAYER_BANNER);
+    *brush.into_inner() = editor_brick_sprite(&game, editor.brush);
+
+    // Level info
+    let mut text = info.into_inner();
+    text.0 = format!(
+        "LEVEL EDITOR\n\nLevel #{}\n{}\n\n{}",
+        editor.level + 1,
+        if editor.unsaved.contains(&editor.level) {
+            "(unsaved)"
+        } else {
+            ""
+        },
+        editor.status
+    );
+
+    if !editor.dirty {
+        return;
+    }
+    editor.dirty = false;
+
+    // Spawn bricks again
+    background.into_inner().image = level.background.clone();
+    for entity in &bricks {
+        commands.entity(entity).despawn();
+    }
+    for (grid_idx, &brick) in level.bricks.iter().enumerate() {
+        let Some(variant) = brick else {
+            continue;
+        };
+        let (x, y) = game_rc_to_xy(grid_idx / GRID_COLS, grid_idx % GRID_COLS);
+        let brick_entity_id = commands
+            .spawn((
+                editor_brick_sprite(&game, variant),
+                Transform::from_xyz(x, y, LAYER_BRICKS),
+                EditorBrick,
+                EditorTag,
+            ))
+            .id();
+        let shadow_id = commands
+            .spawn((
+                Sprite::from_image(game.h_brick_shadow.clone()),
+                Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS - LAYER_BRICKS),
+            ))
+            .id();
+        commands.entity(brick_entity_id).add_child(shadow_id);
+    }
+}
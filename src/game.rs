@@ -1,17 +1,23 @@
 use crate::animation::*;
+use crate::audio::*;
 use crate::balls_and_bullets::*;
 use crate::barrels::*;
 use crate::collisions::*;
 use crate::consts::*;
+use crate::controls::*;
 use crate::countdown_and_portal::*;
+use crate::level::*;
 use crate::meanies::*;
 use crate::paddle::*;
+use crate::replay::*;
 use crate::shop::*;
+use crate::sim::*;
 use crate::*;
 use bevy::audio::*;
 use bevy::prelude::*;
-use rand::distr::weighted::WeightedIndex;
-use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::*;
 
 #[derive(Component)]
 /// Tag component to mark entities as part of the game scene
@@ -20,10 +26,7 @@ pub struct GameTag;
 
 #[derive(Component)]
 /// Component for brick entities
-pub struct Brick {
-    /// Brick variant/type (affects appearance and behavior)
-    pub variant: usize,
-}
+pub struct Brick;
 
 #[derive(Component)]
 /// Component for messages displayed in the info area
@@ -32,27 +35,66 @@ pub struct InfoAreaMsg {
     pub timer: Timer,
 }
 
+#[derive(Component)]
+/// Component for displaying the score
+pub struct ScoreDisplay {
+    /// The score currently shown on screen
+    pub score_displayed: u32,
+}
+
+#[derive(Resource)]
+/// Random number generator for every gameplay choice, seeded for reproducible runs
+pub struct GameRng {
+    /// Seed shown in the HUD and given with -s to replay the same run
+    pub seed: u64,
+    /// Generator state
+    pub rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Starts the sequence again, so that a new game repeats the same choices
+    pub fn reset(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+/// Simulation of the level being played. Game systems show its state and events
+pub struct Simulation {
+    #[deref]
+    pub sim: Sim,
+    /// Random number generator of the level, seeded from `GameRng` or a replay
+    pub rng: StdRng,
+}
+
 #[derive(Resource)]
 /// Main game resource that stores all game state and assets
 pub struct Game {
-    /// Grid of brick entities (None for empty spaces)
+    /// Grid of brick entities shown on screen (None for empty spaces)
     pub grid: Vec<Option<Entity>>,
     /// Number of lives remaining
     pub nlives: u32,
-    /// Number of balls currently in play
-    pub nballs: u32,
-    /// Number of enemy meanies currently in play
-    pub nmeanies: u32,
+    /// Points scored in the current game
+    pub score: u32,
+    /// Seconds played in the current game
+    pub play_time: f32,
+    /// Seconds played in the current game when the level started
+    pub level_play_time: f32,
     /// Current level index
     pub current_level: usize,
-    /// Number of bricks remaining to be destroyed
-    pub bricks_left: u32,
-    /// Whether the exit portal is currently open
-    pub portal_open: bool,
-    /// Seconds remaining on the level timer
+    /// Number of levels in the campaign (known once the campaign is loaded)
+    pub nlevels: usize,
+    /// Seconds remaining on the level timer when the level ended
     pub seconds_left: f32,
-    /// Average time between frame updates, used for stability
-    pub avg_delta: f32,
+    /// Whether the level is being play-tested from the level editor
+    pub playtest: bool,
 
     // Asset handles
     /// Menu background image
@@ -65,8 +107,6 @@ pub struct Game {
     pub h_ball: Handle<Image>,
     /// Bullet image
     pub h_bullet: Handle<Image>,
-    /// Level background images
-    pub h_backgrounds: Vec<Handle<Image>>,
     /// Animation frames for brick destruction
     pub h_brick_frames: Vec<Vec<Handle<Image>>>,
     /// Animation frames for barrel power-ups
@@ -79,13 +119,20 @@ pub struct Game {
     pub h_portal_top_left: Handle<Image>,
     /// Top-right portal image
     pub h_portal_top_right: Handle<Image>,
+    /// Animation frames for the doors of the meanies portals, from closed to open
+    pub h_meanies_portal_frames: Vec<Vec<Handle<Image>>>,
     /// Animation frames for ball impacts
     pub h_ball_impact_frames: Vec<Handle<Image>>,
     /// Animation frames for bullet impacts
     pub h_bullet_impact_frames: Vec<Handle<Image>>,
+    /// Animation frames for the game over banner
+    pub h_gameover_frames: Vec<Handle<Image>>,
+    /// Animation frames for the start of game banner
+    pub h_start_frames: Vec<Handle<Image>>,
     /// Animation frames for enemy meanies
     pub h_meanies_frames: Vec<Vec<Handle<Image>>>,
-    // pub h_meanies_shadows: Vec<Vec<Handle<Image>>>,
+    /// Shadow frames for enemy meanies, matching their animation frames
+    pub h_meanies_shadows: Vec<Vec<Handle<Image>>>,
     /// Shadow images for paddle variants
     pub h_paddle_shadows: Vec<Handle<Image>>,
     /// Shadow image for bricks
@@ -96,34 +143,46 @@ pub struct Game {
     pub h_barrel_shadow: Handle<Image>,
     /// Game logo image
     pub h_logo: Handle<Image>,
-    /// Probability distribution for barrel types
-    pub barrel_dist: WeightedIndex<u32>,
+    /// Life icon image
+    pub h_life: Handle<Image>,
+    /// Empty image, to make sprites blink
+    pub h_blank: Handle<Image>,
+    /// Levels played in a game
+    pub campaign: Handle<Campaign>,
+    /// Weights of the barrel types, from the unlocked secrets
+    pub barrel_weights: Vec<u32>,
 
     // Audio assets
     /// Main theme music
     pub music_main_theme: Handle<AudioSource>,
     /// Arkanoid theme music
     pub music_arkanoid: Handle<AudioSource>,
-    /// Wall hit sound effect
-    pub sound_hit_wall: Handle<AudioSource>,
-    /// Brick hit sound effect
-    pub sound_hit_brick: Handle<AudioSource>,
+    /// Wall hit sound effects, for slow to very fast balls
+    pub sound_hit_wall: Vec<Handle<AudioSource>>,
+    /// Brick hit sound effects, for slow to very fast balls
+    pub sound_hit_brick: Vec<Handle<AudioSource>>,
     /// Bullet firing sound effect
     pub sound_fire_bullet: Handle<AudioSource>,
-    /// Paddle hit sound effect
-    pub sound_paddle: Handle<AudioSource>,
+    /// Paddle hit sound effects, for slow to very fast balls
+    pub sound_paddle: Vec<Handle<AudioSource>>,
     /// Magnet paddle catch sound effect
     pub sound_magnet: Handle<AudioSource>,
     /// Bullet hit sound effects (randomly selected)
     pub sound_bullet_hit: Vec<Handle<AudioSource>>,
-    /// Chime sound effect (used in level 3)
-    pub sound_chime: Handle<AudioSource>,
     /// Level start sound effect
     pub sound_start: Handle<AudioSource>,
+    /// Game start sound effect
+    pub sound_start_game: Handle<AudioSource>,
+    /// Life lost sound effect
+    pub sound_lose_life: Handle<AudioSource>,
+    /// Game over sound effect
+    pub sound_game_over: Handle<AudioSource>,
     /// Portal sound effect
     pub sound_portal: Handle<AudioSource>,
     /// Barrel power-up sound effects
     pub sound_barrels: Vec<Handle<AudioSource>>,
+    /// Menu selection sound effect
+    pub sound_menu: Handle<AudioSource>,
 
     // Player progress
     /// List of levels the player has unlocked
@@ -132,44 +191,71 @@ pub struct Game {
     pub secrets_unlocked: Vec<String>,
     /// List of secrets generated in the current game
     pub secrets_generated: Vec<String>,
-    /// Player's username
-    pub username: String,
+    /// Letters identifying the player in the codes, generated on the first run
+    pub player_id: String,
+    /// Name chosen by the player on the first run (empty until then)
+    pub handle: String,
 }
 
 /// Registers all game systems with the Bevy app
 pub fn game_plugin(app: &mut App) {
     app.add_systems(OnEnter(GameState::Game), game_enter)
         .add_systems(OnExit(GameState::Game), despawn_all::<GameTag>)
+        .add_systems(
+            PreUpdate,
+            paddle_mouse.run_if(in_state(GameState::Game)),
+        )
+        .add_systems(
+            FixedUpdate,
+            game_update.run_if(in_state(PauseState::Running)),
+        )
         .add_systems(
             Update,
             (
-                paddle_update,
-                ball_update,
-                bullets_update,
-                barrels_update,
-                portal_update,
-                meanies_update,
+                game_events,
+                (
+                    paddle_sync,
+                    balls_sync,
+                    bullets_sync,
+                    barrels_sync,
+                    effects_sync,
+                    meanies_sync,
+                    countdown_update,
+                    lives_update,
+                    score_update,
+                ),
                 animate,
-                countdown_update,
-                lives_update,
             )
+                .chain()
                 .run_if(in_state(GameState::Game)),
         );
 }
 
 /// Initialization when entering a new level (i.e. enters Game state).
 /// Creates the paddle, ball, bricks, portal, and UI elements
-pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
+#[allow(clippy::too_many_arguments)]
+pub fn game_enter(
+    mut commands: Commands,
+    mut audio: Audio,
+    mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    player: Option<Res<ReplayPlayer>>,
+    time: Res<Time<Fixed>>,
+    campaigns: Res<Assets<Campaign>>,
+    levels: Res<Assets<Level>>,
+) {
+    let Some(level) = campaign_level(&game.campaign, game.current_level, &campaigns, &levels)
+    else {
+        error!("Level {} is not loaded", game.current_level);
+        return;
+    };
+
     // Create Paddle
-    let paddle_variant = 0;
     let paddle = commands
         .spawn((
             Sprite::from_image(game.h_paddles[0].clone()),
             Transform::from_xyz(GAMEAREA_CENTER_X, PADDLE_Y, LAYER_PADDLE),
-            Paddle {
-                variant: paddle_variant,
-                gun_timer: Timer::new(Duration::from_millis(GUN_TIMER_MS), TimerMode::Once),
-            },
+            Paddle { variant: 0 },
             GameTag,
         ))
         .id();
@@ -182,15 +268,8 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
         .id();
     commands.entity(paddle).add_child(paddle_shadow);
 
-    // Create Initial Ball
-    game.nballs = 0;
-    spawn_new_ball(&mut commands, paddle_variant, GAMEAREA_CENTER_X, &mut game);
-
-    // Reset Meanies
-    game.nmeanies = 0;
-
     // Countdown
-    game.seconds_left = LEVEL_TIMERS[game.current_level];
+    game.seconds_left = level.timer;
     let mut msg: String = String::from("Beat Timer\nto Discover\nSecret");
     let mut color: Color = Color::WHITE;
     if level_is_unlocked(game.current_level, &game) {
@@ -198,10 +277,10 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
         msg = String::from("\nExit!");
         game.seconds_left = UNLOCKED_PORTAL_TIMER;
         color = Color::Srgba(Srgba::new(0.1, 1.0, 0.1, 1.0));
-    } else if secret_is_discovered(game.current_level, &game) {
+    } else if game.current_level < NSECRETS && secret_is_discovered(game.current_level, &game) {
         // Harder countdown if secret is unlocked but level is not
         msg = String::from("Beat Time\nto Unlock\nLevel");
-        game.seconds_left = LEVEL_TIMERS[game.current_level] - 30.;
+        game.seconds_left = level.timer - 30.;
         color = Color::Srgba(Srgba::new(1.0, 0.1, 0.1, 1.0));
     }
     commands.spawn((
@@ -212,7 +291,7 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
     ));
     commands.spawn((
         Countdown {
-            timer: Timer::from_seconds(game.seconds_left, TimerMode::Once),
+            seconds_displayed: game.seconds_left,
         },
         Text2d::new(format!("{:.0}", game.seconds_left).as_str()),
         TextFont {
@@ -236,30 +315,20 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
         GameTag,
     ));
 
-    // Create Portal
-    game.portal_open = false;
-    let portal_state: PortalState = if level_is_unlocked(game.current_level, &game) {
-        commands.spawn((
-            AudioPlayer::new(game.sound_portal.clone()),
-            PlaybackSettings::DESPAWN,
-        ));
-        PortalState::Opening
-    } else {
-        PortalState::Closed
-    };
+    // Create Portal (opened and closed by the simulation events)
     commands.spawn((
         Sprite::from_image(game.h_portal_frames[0].clone()),
         Transform::from_xyz(GAMEAREA_MAXX - 55., GAMEAREA_MINY + 35., LAYER_PORTAL_BG),
         Animation {
             timer: Timer::from_seconds(1.0 / PORTAL_FRAMERATE, TimerMode::Repeating),
-            frozen: matches!(portal_state, PortalState::Closed),
+            frozen: true,
             variant: AnimationType::Freeze,
             frames: game.h_portal_frames.clone(),
             current_frame: 0,
             reverse: false,
             velocity: Vec2::ZERO,
         },
-        Portal { portal_state },
+        Portal,
         GameTag,
     ));
     commands.spawn((
@@ -268,7 +337,24 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
         GameTag,
     ));
 
-    // Meanies portals
+    // Meanies portals, whose doors open while a meanie comes through
+    for (idx, &x) in MEANIES_PORTAL_X.iter().enumerate() {
+        commands.spawn((
+            Sprite::from_image(game.h_meanies_portal_frames[idx][0].clone()),
+            Transform::from_xyz(x, MEANIES_PORTAL_DOOR_Y, LAYER_PORTAL_BG),
+            Animation {
+                timer: Timer::from_seconds(1.0 / PORTAL_FRAMERATE, TimerMode::Repeating),
+                frozen: true,
+                variant: AnimationType::Freeze,
+                frames: game.h_meanies_portal_frames[idx].clone(),
+                current_frame: 0,
+                reverse: false,
+                velocity: Vec2::ZERO,
+            },
+            MeaniePortal { idx, open: false },
+            GameTag,
+        ));
+    }
     commands.spawn((
         Sprite::from_image(game.h_portal_top_left.clone()),
         Transform::from_xyz(MEANIES_PORTAL_X[0], GAMEAREA_MAXY - 20., LAYER_PORTAL_FG),
@@ -290,90 +376,333 @@ pub fn game_enter(mut commands: Commands, mut game: ResMut<Game>) {
     // Lives
     if game.current_level == 0 {
         game.nlives = NLIVES;
+        game.score = 0;
+        game.play_time = 0.;
     }
+    game.level_play_time = game.play_time;
+    commands.spawn((
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_LIVES_Y, LAYER_BANNER),
+        Visibility::default(),
+        LivesDisplay {
+            nlives_displayed: 0,
+        },
+        GameTag,
+    ));
+
+    // Score
     commands.spawn((
-        Text2d::new("Lives: -"),
+        Text2d::new("Score: 0"),
         TextFont {
             font_size: 20.0,
             ..default()
         },
-        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_LIVES_Y, LAYER_BANNER),
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_SCORE_Y, LAYER_BANNER),
         TextLayout::new_with_justify(Justify::Center),
-        LivesDisplay {
-            nlives_displayed: 0,
+        ScoreDisplay { score_displayed: 0 },
+        GameTag,
+    ));
+
+    // Seed
+    let seed_text = match player {
+        Some(_) => "Replay".to_string(),
+        None => format!("Seed: {}", rng.seed),
+    };
+    commands.spawn((
+        Text2d::new(seed_text),
+        TextFont {
+            font_size: 12.0,
+            ..default()
         },
+        TextColor(Color::srgb(0.6, 0.6, 0.6)),
+        Transform::from_xyz(INFOAREA_CENTER_X, INFOAREA_SEED_Y, LAYER_BANNER),
+        TextLayout::new_with_justify(Justify::Center),
         GameTag,
     ));
 
     // Background image
     commands.spawn((
-        Sprite::from_image(game.h_backgrounds[game.current_level].clone()),
+        Sprite::from_image(level.background.clone()),
         Transform::from_xyz(GAMEAREA_CENTER_X, GAMEAREA_CENTER_Y, LAYER_BG),
         GameTag,
     ));
 
+    // Level settings, from the replay being played or from the level and player progress
+    let (config, seed) = match &player {
+        Some(player) => (player.replay.config.clone(), player.replay.seed),
+        None => {
+            let config = SimConfig {
+                bricks: level.bricks.clone(),
+                timer: game.seconds_left,
+                nlives: game.nlives,
+                score: game.score,
+                portal_unlocked: level_is_unlocked(game.current_level, &game),
+                meanies: secret_is_unlocked(Secret::Meanies as usize, &game),
+                barrel_weights: game.barrel_weights.clone(),
+                powerup_duration: level.powerup_duration,
+            };
+            (config, rng.rng.random())
+        }
+    };
+
     // Populate Bricks
-    game.bricks_left = 0;
-    let level = LEVELS[game.current_level];
-    for (r, &row) in level.iter().enumerate() {
-        for (c, &brick) in row.iter().enumerate() {
-            let grid_idx: usize = r * GRID_COLS + c;
-
-            // Skip empty slots
-            if brick == b' ' {
-                game.grid[grid_idx] = None;
-                continue;
-            }
+    for (grid_idx, &brick) in config.bricks.iter().enumerate() {
+        // Skip empty slots
+        let Some(variant) = brick else {
+            game.grid[grid_idx] = None;
+            continue;
+        };
 
-            // convert hex character to integer
-            let variant = if brick <= b'9' {
-                brick - b'0'
-            } else {
-                brick - b'a' + 10
-            } as usize;
+        // Spawn brick
+        let (x, y) = game_rc_to_xy(grid_idx / GRID_COLS, grid_idx % GRID_COLS);
+        let brick_entity_id = commands
+            .spawn((
+                Sprite::from_image(game.h_bricks[variant].clone()),
+                Transform::from_xyz(x, y, LAYER_BRICKS),
+                Brick,
+                GameTag,
+            ))
+            .id();
 
-            // Spawn brick
-            let (x, y) = game_rc_to_xy(r, c);
-            let brick_entity_id = commands
+        // All bricks except 14 have a shadow
+        if variant != 14 || secret_is_unlocked(Secret::XRay as usize, &game) {
+            let shadow_id = commands
                 .spawn((
-                    Sprite::from_image(game.h_bricks[variant].clone()),
-                    Transform::from_xyz(x, y, LAYER_BRICKS),
-                    Brick { variant },
-                    GameTag,
+                    Sprite::from_image(game.h_brick_shadow.clone()),
+                    Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS - LAYER_BRICKS),
                 ))
                 .id();
-
-            // All bricks except 14 have a shadow
-            if variant != 14 || secret_is_unlocked(Secret::XRay as usize, &game) {
-                let shadow_id = commands
-                    .spawn((
-                        Sprite::from_image(game.h_brick_shadow.clone()),
-                        Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS - LAYER_BRICKS),
-                    ))
-                    .id();
-                commands.entity(brick_entity_id).add_child(shadow_id);
-            }
-
-            // Add brick to grid
-            game.grid[grid_idx] = Some(brick_entity_id);
-            if variant != 13 {
-                game.bricks_left += 1;
-            }
+            commands.entity(brick_entity_id).add_child(shadow_id);
         }
+
+        // Add brick to grid
+        game.grid[grid_idx] = Some(brick_entity_id);
     }
 
-    if game.current_level == 3 {
-        // Annoying chime sound in level 3
+    // Start simulation and its recording
+    let tick_hz = 1. / time.timestep().as_secs_f64();
+    commands.insert_resource(ReplayRecorder(Replay::new(
+        game.current_level,
+        tick_hz,
+        seed,
+        config.clone(),
+    )));
+    commands.insert_resource(Simulation {
+        sim: Sim::new(config),
+        rng: StdRng::seed_from_u64(seed),
+    });
+
+    // Start of game banner
+    let new_game = game.current_level == 0 && !game.playtest && player.is_none();
+    if new_game {
         commands.spawn((
-            AudioPlayer::new(game.sound_chime.clone()),
-            PlaybackSettings::LOOP.with_volume(Volume::Linear(5.0)),
+            Sprite::from_image(game.h_start_frames[0].clone()),
+            Transform::from_xyz(GAMEAREA_CENTER_X, GAMEAREA_CENTER_Y, LAYER_BANNER),
+            Animation {
+                timer: Timer::from_seconds(1.0 / START_FRAMERATE, TimerMode::Repeating),
+                frozen: false,
+                variant: AnimationType::Despawn,
+                frames: game.h_start_frames.clone(),
+                current_frame: 0,
+                reverse: false,
+                velocity: Vec2::ZERO,
+            },
             GameTag,
         ));
+    }
+
+    if let Some(music) = &level.music {
+        // Level music (e.g. the annoying chime in level 4)
+        audio.music(music, level.music_volume).insert(GameTag);
+    } else if new_game {
+        audio.play(AudioBus::Sfx, &game.sound_start_game);
     } else {
         // Regular start level tune for the rest
-        commands.spawn((
-            AudioPlayer::new(game.sound_start.clone()),
-            PlaybackSettings::DESPAWN,
-        ));
+        audio.play(AudioBus::Sfx, &game.sound_start);
+    }
+}
+
+/// System that advances the simulation one fixed step with the player input
+/// (or the replay being played) and ends the level when the simulation says so
+#[allow(clippy::too_many_arguments)]
+pub fn game_update(
+    mut commands: Commands,
+    actions: Actions,
+    mouse: Res<PaddleMouse>,
+    time: Res<Time<Fixed>>,
+    mut simulation: ResMut<Simulation>,
+    mut recorder: ResMut<ReplayRecorder>,
+    player: Option<ResMut<ReplayPlayer>>,
+    mut game: ResMut<Game>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    // Several steps may run before the state changes, but the level ends only once
+    if simulation.status != SimStatus::Playing {
+        return;
+    }
+
+    let playback = player.is_some();
+    let step_input = match player {
+        Some(mut player) => player.next_input(),
+        None => Some(paddle_input(&actions, &mouse)),
+    };
+    let Some(step_input) = step_input else {
+        // Replay is over, back to normal play
+        commands.remove_resource::<ReplayPlayer>();
+        game_state.set(GameState::Menu);
+        return;
+    };
+    recorder.push(step_input);
+    let Simulation { sim, rng } = &mut *simulation;
+    sim.step(time.delta_secs(), &step_input, rng);
+
+    game.nlives = sim.nlives;
+    game.score = sim.score;
+    game.play_time += time.delta_secs();
+    game.seconds_left = sim.time_left.ceil();
+    if sim.status == SimStatus::Playing {
+        return;
+    }
+
+    // Replays do not count as progress
+    if playback {
+        commands.remove_resource::<ReplayPlayer>();
+        game_state.set(GameState::Menu);
+        return;
+    }
+    // Play-tests of the editor do not replace the replay of the last real game
+    if !game.playtest {
+        replay_save_last(&recorder);
+    }
+    if sim.status == SimStatus::LevelExit {
+        game.current_level += 1;
+    }
+    game_state.set(GameState::Transition);
+}
+
+/// System that shows the simulation events with sounds, animations and messages
+pub fn game_events(
+    mut commands: Commands,
+    portal_comp: Single<&mut Animation, With<Portal>>,
+    msg_comp: Single<(&mut Text2d, &mut InfoAreaMsg)>,
+    mut sim: ResMut<Simulation>,
+    mut game: ResMut<Game>,
+    mut audio: Audio,
+) {
+    let mut anim_portal = portal_comp.into_inner();
+    let (mut text, mut text_timer) = msg_comp.into_inner();
+    let mut sounds: Vec<Handle<AudioSource>> = Vec::new();
+    // Sounds panned to where they happened, with their playback speed
+    let mut impacts: Vec<(Handle<AudioSource>, f32, f32)> = Vec::new();
+    let mut explosions: Vec<(Vec<Handle<Image>>, Vec2)> = Vec::new();
+    for event in std::mem::take(&mut sim.events) {
+        match event {
+            SimEvent::WallHit { pos, speed } => {
+                explosions.push((game.h_ball_impact_frames.clone(), pos));
+                let sound = hit_sound(&game.sound_hit_wall, speed);
+                impacts.push((sound, pos.x, hit_pitch(speed)));
+            }
+            SimEvent::PaddleHit { pos, speed } => {
+                impacts.push((hit_sound(&game.sound_paddle, speed), pos.x, 1.));
+            }
+            SimEvent::BallCaught(pos) => impacts.push((game.sound_magnet.clone(), pos.x, 1.)),
+            SimEvent::BallHitBricks { pos, speed } => {
+                let sound = hit_sound(&game.sound_hit_brick, speed);
+                impacts.push((sound, pos.x, hit_pitch(speed)));
+            }
+            SimEvent::BrickHit { idx, variant } => {
+                let pos = game_rc_to_xy(idx / GRID_COLS, idx % GRID_COLS).into();
+                explosions.push((game.h_brick_frames[variant].clone(), pos));
+            }
+            SimEvent::BrickDestroyed { idx } => {
+                if let Some(brick_entity) = game.grid[idx].take() {
+                    commands.entity(brick_entity).despawn();
+                }
+            }
+            SimEvent::BulletsFired => sounds.push(game.sound_fire_bullet.clone()),
+            SimEvent::BulletHit(pos) => {
+                explosions.push((game.h_bullet_impact_frames.clone(), pos));
+                let sound = game.sound_bullet_hit.choose(&mut rand::rng()).unwrap();
+                impacts.push((sound.clone(), pos.x, 1.));
+            }
+            SimEvent::BarrelCaught { variant } => {
+                *text = Text2d::new(BARREL_TITLES[variant]);
+                text_timer.timer.reset();
+                sounds.push(game.sound_barrels[variant].clone());
+            }
+            SimEvent::SpeedChanged { speed } => {
+                *text = Text2d::new(format!(
+                    "Speed {}%",
+                    (speed / BALL_INITIAL_SPEED * 100.).round()
+                ));
+                text_timer.timer.reset();
+            }
+            SimEvent::Combo { multiplier } => {
+                *text = Text2d::new(format!("Combo x{multiplier}"));
+                text_timer.timer.reset();
+            }
+            SimEvent::BallNudged { speed } => {
+                *text = Text2d::new(format!(
+                    "Nudging Ball {}%",
+                    (speed / BALL_INITIAL_SPEED * 100.).round()
+                ));
+                text_timer.timer.reset();
+            }
+            SimEvent::MeanieDestroyed { pos, shot } => {
+                explosions.push((game.h_bullet_impact_frames.clone(), pos));
+                if shot {
+                    let sound = game.sound_bullet_hit.choose(&mut rand::rng()).unwrap();
+                    impacts.push((sound.clone(), pos.x, 1.));
+                }
+            }
+            SimEvent::LifeLost(pos) => {
+                explosions.push((game.h_bullet_impact_frames.clone(), pos));
+                impacts.push((game.sound_lose_life.clone(), pos.x, 1.));
+            }
+            SimEvent::PortalOpening | SimEvent::PortalClosing => {
+                anim_portal.frozen = false;
+                anim_portal.reverse = matches!(event, SimEvent::PortalClosing);
+                sounds.push(game.sound_portal.clone());
+            }
+            SimEvent::ExtraLife
+            | SimEvent::CountdownFinished
+            | SimEvent::LevelExit
+            | SimEvent::GameOver => {}
+        }
+    }
+
+    for sound in sounds {
+        audio.play(AudioBus::Sfx, &sound);
+    }
+    for (sound, x, speed) in impacts {
+        audio.play_at(AudioBus::Sfx, &sound, x, speed);
+    }
+    for (frames, pos) in explosions {
+        spawn_explosion(&mut commands, &frames, pos);
+    }
+}
+
+/// Ball speed from 0 (initial speed or slower) to 1 (maximum speed)
+fn hit_speed(speed: f32) -> f32 {
+    ((speed - BALL_INITIAL_SPEED) / (BALL_MAX_SPEED - BALL_INITIAL_SPEED)).clamp(0., 1.)
+}
+
+/// Impact sound for the speed of the ball, harder for faster balls
+fn hit_sound(sounds: &[Handle<AudioSource>], speed: f32) -> Handle<AudioSource> {
+    let n = sounds.len();
+    sounds[((hit_speed(speed) * n as f32) as usize).min(n - 1)].clone()
+}
+
+/// Playback speed of a ball impact sound, higher for faster balls
+fn hit_pitch(speed: f32) -> f32 {
+    AUDIO_HIT_PITCH[0] + (AUDIO_HIT_PITCH[1] - AUDIO_HIT_PITCH[0]) * hit_speed(speed)
+}
+
+/// System that updates the score display when the score changes
+pub fn score_update(score_comp: Single<(&mut Text2d, &mut ScoreDisplay)>, sim: Res<Simulation>) {
+    let (mut text, mut display) = score_comp.into_inner();
+    if display.score_displayed != sim.score {
+        text.clear();
+        text.push_str(format!("Score: {}", sim.score).as_str());
+        display.score_displayed = sim.score;
     }
 }
@@ -0,0 +1,93 @@
+use crate::consts::*;
+use crate::save::*;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+/// Kinds of game with separate high-score tables
+pub enum GameMode {
+    /// Random seed on every run
+    Normal,
+    /// Seed given with -s (e.g. daily challenges), with a table for each seed
+    Seed(u64),
+}
+
+impl GameMode {
+    pub fn title(&self) -> String {
+        match self {
+            GameMode::Normal => "NORMAL".to_string(),
+            GameMode::Seed(seed) => format!("SEED {seed}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+/// One entry of a high-score table
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// Last level played (1 is the first level)
+    pub level: usize,
+    /// Seconds played in the game
+    pub seconds: u32,
+    /// Local date and time when the game ended
+    pub date: String,
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+/// Best scores for every game mode, best first
+pub struct HighScores {
+    tables: BTreeMap<GameMode, Vec<HighScore>>,
+}
+
+impl HighScores {
+    pub fn save(&self) {
+        save_update(|save| save.highscores = self.clone());
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScore] {
+        self.tables.get(&mode).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether a score is good enough to enter the table
+    pub fn qualifies(&self, mode: GameMode, score: u32) -> bool {
+        let table = self.table(mode);
+        score > 0 && (table.len() < HIGHSCORES_LEN || table.iter().any(|h| score > h.score))
+    }
+
+    /// Adds an entry, keeping the table sorted and at most `HIGHSCORES_LEN` long
+    pub fn insert(&mut self, mode: GameMode, entry: HighScore) {
+        let table = self.tables.entry(mode).or_default();
+        let rank = table.partition_point(|h| h.score >= entry.score);
+        table.insert(rank, entry);
+        table.truncate(HIGHSCORES_LEN);
+    }
+
+    /// Text of a table to show on screen
+    pub fn format(&self, mode: GameMode) -> String {
+        let mut text = format!("HIGH SCORES ({})\n\n", mode.title());
+        if self.table(mode).is_empty() {
+            text.push_str("No scores yet\n");
+        }
+        for (rank, h) in self.table(mode).iter().enumerate() {
+            text.push_str(&format!(
+                "{:>2}. {:<HIGHSCORE_NAME_LEN$} {:>7}  L{:<2} {:02}:{:02}  {}\n",
+                rank + 1,
+                h.name,
+                h.score,
+                h.level,
+                h.seconds / 60,
+                h.seconds % 60,
+                h.date,
+            ));
+        }
+        text
+    }
+}
+
+/// Local date and time in the format stored in the tables
+pub fn highscore_date() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M").to_string()
+}
@@ -0,0 +1,230 @@
+use crate::consts::*;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AssetPath, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Serialize, Deserialize, Clone)]
+/// On-disk description of a level (`*.level.ron` files)
+pub struct LevelFile {
+    /// Title shown in the banner before the level starts
+    pub title: String,
+    /// Banner color (red, green, blue)
+    pub color: (f32, f32, f32),
+    /// Seconds on the countdown to discover the level secret
+    pub timer: f32,
+    /// Background image, relative to the assets folder
+    pub background: String,
+    /// Music looped during the level. If missing, the start tune is played
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub music: Option<String>,
+    /// Linear volume of the level music
+    #[serde(default = "default_music_volume")]
+    pub music_volume: f32,
+    /// Seconds that paddle and speed barrels last. If missing, they last until
+    /// the next barrel or the next life
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub powerup_duration: Option<f32>,
+    /// Brick layout, one string per row. See the brick legend in `consts.rs`
+    pub grid: Vec<String>,
+}
+
+fn default_music_volume() -> f32 {
+    1.0
+}
+
+#[derive(Asset, TypePath)]
+/// A level ready to be played, with its assets already requested
+pub struct Level {
+    /// Title shown in the banner before the level starts
+    pub title: String,
+    /// Banner color
+    pub color: Srgba,
+    /// Seconds on the countdown to discover the level secret
+    pub timer: f32,
+    /// Brick variant for every grid slot (None for empty slots)
+    pub bricks: Vec<Option<usize>>,
+    /// Background image
+    pub background: Handle<Image>,
+    /// Music looped during the level
+    pub music: Option<Handle<AudioSource>>,
+    /// Linear volume of the level music
+    pub music_volume: f32,
+    /// Seconds that paddle and speed barrels last
+    pub powerup_duration: Option<f32>,
+}
+
+impl Level {
+    /// Converts the level back into its on-disk description
+    pub fn to_file(&self) -> LevelFile {
+        let asset_file = |path: &AssetPath| path.path().to_string_lossy().into_owned();
+        LevelFile {
+            title: self.title.clone(),
+            color: (self.color.red, self.color.green, self.color.blue),
+            timer: self.timer,
+            background: self.background.path().map(asset_file).unwrap_or_default(),
+            music: self
+                .music
+                .as_ref()
+                .and_then(|music| music.path())
+                .map(asset_file),
+            music_volume: self.music_volume,
+            powerup_duration: self.powerup_duration,
+            grid: grid_format(&self.bricks),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+/// On-disk list of the levels in a campaign (`*.campaign.ron` files)
+struct CampaignFile {
+    levels: Vec<String>,
+}
+
+#[derive(Asset, TypePath)]
+/// Ordered list of levels played in a game
+pub struct Campaign {
+    pub levels: Vec<Handle<Level>>,
+}
+
+#[derive(Debug, Error)]
+/// Errors found while loading a level or campaign file
+pub enum LevelError {
+    #[error("could not read level file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse level file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("expected {GRID_ROWS} rows in the grid, found {0}")]
+    WrongRowCount(usize),
+    #[error("expected {GRID_COLS} columns in row {0}, found {1}")]
+    WrongColCount(usize, usize),
+    #[error("invalid brick {2:?} at row {0}, column {1}")]
+    InvalidBrick(usize, usize, char),
+}
+
+/// Converts the rows of a level file into brick variants, one per grid slot
+pub fn grid_parse(rows: &[String]) -> Result<Vec<Option<usize>>, LevelError> {
+    if rows.len() != GRID_ROWS {
+        return Err(LevelError::WrongRowCount(rows.len()));
+    }
+    let mut bricks = Vec::with_capacity(GRID_ROWS * GRID_COLS);
+    for (r, row) in rows.iter().enumerate() {
+        if row.chars().count() != GRID_COLS {
+            return Err(LevelError::WrongColCount(r, row.chars().count()));
+        }
+        for (c, brick) in row.chars().enumerate() {
+            if brick == ' ' {
+                bricks.push(None);
+                continue;
+            }
+            match brick.to_digit(16) {
+                Some(variant) if (variant as usize) < BRICK_TYPES => {
+                    bricks.push(Some(variant as usize));
+                }
+                _ => return Err(LevelError::InvalidBrick(r, c, brick)),
+            }
+        }
+    }
+    Ok(bricks)
+}
+
+/// Converts brick variants back into the rows of a level file
+pub fn grid_format(bricks: &[Option<usize>]) -> Vec<String> {
+    bricks
+        .chunks(GRID_COLS)
+        .map(|row| {
+            row.iter()
+                .map(|brick| match brick {
+                    Some(variant) => char::from_digit(*variant as u32, 16).unwrap_or(' '),
+                    None => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[derive(Default, TypePath)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Level, LevelError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: LevelFile = ron::de::from_bytes(&bytes)?;
+        let (red, green, blue) = file.color;
+        Ok(Level {
+            title: file.title,
+            color: Srgba::new(red, green, blue, 1.0),
+            timer: file.timer,
+            bricks: grid_parse(&file.grid)?,
+            background: load_context.load(file.background),
+            music: file.music.map(|music| load_context.load(music)),
+            music_volume: file.music_volume,
+            powerup_duration: file.powerup_duration,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[derive(Default, TypePath)]
+struct CampaignLoader;
+
+impl AssetLoader for CampaignLoader {
+    type Asset = Campaign;
+    type Settings = ();
+    type Error = LevelError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Campaign, LevelError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: CampaignFile = ron::de::from_bytes(&bytes)?;
+        Ok(Campaign {
+            levels: file
+                .levels
+                .into_iter()
+                .map(|path| load_context.load(path))
+                .collect(),
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["campaign.ron"]
+    }
+}
+
+/// Registers the level and campaign asset types with the Bevy app
+pub fn level_plugin(app: &mut App) {
+    app.init_asset::<Level>()
+        .init_asset::<Campaign>()
+        .init_asset_loader::<LevelLoader>()
+        .init_asset_loader::<CampaignLoader>();
+}
+
+/// Returns level `idx` of the campaign, if the campaign and the level are loaded
+pub fn campaign_level<'a>(
+    campaign: &Handle<Campaign>,
+    idx: usize,
+    campaigns: &Assets<Campaign>,
+    levels: &'a Assets<Level>,
+) -> Option<&'a Level> {
+    let campaign = campaigns.get(campaign)?;
+    levels.get(campaign.levels.get(idx)?)
+}
@@ -1,20 +1,29 @@
 mod animation;
+mod audio;
 mod balls_and_bullets;
 mod barrels;
+mod codes;
 mod collisions;
 mod consts;
+mod controls;
 mod countdown_and_portal;
+mod editor;
 mod game;
+mod highscores;
+mod level;
 mod meanies;
 mod menu;
 mod paddle;
+mod pause;
+mod replay;
 mod save;
+mod settings;
 mod shop;
+mod sim;
 mod splash;
 mod transition;
 
 use crate::consts::*;
-use crate::shop::*;
 use bevy::prelude::*;
 use bevy::window::*;
 use std::env;
@@ -27,6 +36,16 @@ enum GameState {
     Shop,
     Game,
     Transition,
+    Editor,
+}
+
+#[derive(SubStates, Default, PartialEq, Eq, Copy, Hash, Clone, Debug)]
+#[source(GameState = GameState::Game)]
+/// Whether a level being played is paused
+enum PauseState {
+    #[default]
+    Running,
+    Paused,
 }
 
 fn main() {
@@ -39,35 +58,118 @@ fn main() {
         let codes: Vec<&String> = args.iter().skip(c_index + 1).collect();
 
         for string in codes {
-            println!("{}", decode(string).unwrap());
+            match codes::Code::parse(string) {
+                Ok(code) => println!("{code}"),
+                Err(err) => println!("{string}: {err}"),
+            }
         }
         return; // Exit after handling the command
     }
 
+    // Check for -t flag to change the simulation tick rate
+    let tick_hz: f64 = args
+        .iter()
+        .position(|arg| arg == "-t")
+        .and_then(|t_index| args.get(t_index + 1))
+        .and_then(|hz| hz.parse().ok())
+        .filter(|hz| *hz > 0.)
+        .unwrap_or(SIM_TICK_HZ);
+
+    // Check for -s flag to replay a run with a known seed
+    let seed: Option<u64> = args
+        .iter()
+        .position(|arg| arg == "-s")
+        .and_then(|s_index| args.get(s_index + 1))
+        .and_then(|seed| seed.parse().ok());
+
+    // Seeded runs get a high-score table for each seed
+    let game_mode = match seed {
+        Some(seed) => highscores::GameMode::Seed(seed),
+        None => highscores::GameMode::Normal,
+    };
+    let seed = seed.unwrap_or_else(rand::random);
+
+    // Check for -m flag to move the paddle with the mouse
+    let paddle_mouse = paddle::PaddleMouse {
+        enabled: args.iter().any(|arg| arg == "-m"),
+        ..default()
+    };
+
+    // Check for -p flag to play a replay file
+    let mut player: Option<replay::ReplayPlayer> = None;
+    if let Some(p_index) = args.iter().position(|arg| arg == "-p") {
+        let path = args
+            .get(p_index + 1)
+            .map(String::as_str)
+            .unwrap_or_default();
+        match replay::Replay::load(std::path::Path::new(path)) {
+            Ok(replay) => player = Some(replay::ReplayPlayer::new(replay)),
+            Err(err) => {
+                println!("{path}: {err}");
+                return;
+            }
+        }
+    }
+    let tick_hz = player.as_ref().map_or(tick_hz, |p| p.replay.tick_hz);
+
+    // Check for --profile flag to play with a named profile
+    let mut profile = None;
+    if let Some(p_index) = args.iter().position(|arg| arg == "--profile") {
+        let name = args
+            .get(p_index + 1)
+            .map(String::as_str)
+            .unwrap_or_default();
+        match save::profile_name(name) {
+            Some(name) => profile = Some(name),
+            None => {
+                println!("{name}: profile names have 1 to {PROFILE_NAME_LEN} letters or digits");
+                return;
+            }
+        }
+    }
+
     // Normal game execution if no -c flag
-    App::new()
-        .add_plugins(
-            DefaultPlugins.set(WindowPlugin {
-                primary_window: Some(Window {
-                    resolution: WindowResolution::new(
-                        (2. * SCREEN_WIDTH) as u32,
-                        (2. * SCREEN_HEIGHT) as u32,
-                    )
-                    .with_scale_factor_override(1.0),
-                    ..default()
-                }),
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                resolution: WindowResolution::new(
+                    (2. * SCREEN_WIDTH) as u32,
+                    (2. * SCREEN_HEIGHT) as u32,
+                )
+                .with_scale_factor_override(1.0),
                 ..default()
             }),
-        )
-        .insert_resource(ClearColor(Color::srgb(0.1, 0.1, 0.1)))
-        .init_state::<GameState>()
-        .add_systems(Startup, startup)
-        .add_plugins(splash::splash_plugin)
-        .add_plugins(game::game_plugin)
-        .add_plugins(transition::transition_plugin)
-        .add_plugins(menu::menu_plugin)
-        .add_plugins(shop::shop_plugin)
-        .run();
+            ..default()
+        }),
+    )
+    .insert_resource(ClearColor(Color::srgb(0.1, 0.1, 0.1)))
+    .insert_resource(Time::<Fixed>::from_hz(tick_hz))
+    .insert_resource(game::GameRng::new(seed))
+    .insert_resource(game_mode)
+    .insert_resource(paddle_mouse)
+    .insert_resource(controls::Controls::load())
+    .init_state::<GameState>()
+    .add_sub_state::<PauseState>()
+    .add_systems(Startup, startup)
+    .add_plugins(level::level_plugin)
+    .add_plugins(settings::settings_plugin)
+    .add_plugins(audio::audio_plugin)
+    .add_plugins(splash::splash_plugin)
+    .add_plugins(game::game_plugin)
+    .add_plugins(pause::pause_plugin)
+    .add_plugins(transition::transition_plugin)
+    .add_plugins(menu::menu_plugin)
+    .add_plugins(shop::shop_plugin)
+    .add_plugins(editor::editor_plugin);
+    if let Some(player) = player {
+        app.insert_resource(player);
+    }
+    // Selected once logging is set up, before the splash screen reads the save
+    if let Some(profile) = profile {
+        save::profile_select(&profile);
+    }
+    app.run();
 }
 
 fn startup(mut commands: Commands) {
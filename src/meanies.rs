@@ -1,181 +1,91 @@
 use crate::animation::*;
-use crate::balls_and_bullets::*;
-use crate::collisions::*;
 use crate::game::*;
-use crate::paddle::*;
-use crate::shop::*;
 use crate::*;
-use bevy::math::*;
 use bevy::prelude::*;
-use rand::seq::*;
-use rand::*;
-use std::f32::consts::*;
 
 #[derive(Component)]
+/// Component for enemy meanies, mirroring a simulated meanie
 pub struct Meanie {
-    variant: usize,
-    pub speed: f32,
-    pub angle: f32,
+    /// Identifier of the simulated meanie
+    pub id: u32,
 }
 
-pub fn meanie_destroy(commands: &mut Commands, tr: &Transform, meanie: Entity, game: &mut Game) {
-    commands.spawn((
-        Sprite::from_image(game.h_ball_impact_frames[0].clone()),
-        Transform::from_xyz(tr.translation.x, tr.translation.y, LAYER_EXPLOSIONS),
-        Animation {
-            timer: Timer::from_seconds(1.0 / BRICK_FRAMERATE, TimerMode::Repeating),
-            frozen: false,
-            variant: AnimationType::Despawn,
-            frames: game.h_bullet_impact_frames.clone(),
-            current_frame: 0,
-            reverse: false,
-            velocity: Vec2::ZERO,
-        },
-        GameTag,
-    ));
-    commands.entity(meanie).despawn();
-    game.nmeanies -= 1;
+#[derive(Component)]
+/// Shadow of a meanie, following the frames of its parent
+pub struct MeanieShadow;
+
+#[derive(Component)]
+/// Door of a meanies portal at the top of the game area
+pub struct MeaniePortal {
+    /// Index of the portal in `MEANIES_PORTAL_X`
+    pub idx: usize,
+    /// Whether the door is opening or open
+    pub open: bool,
 }
 
-pub fn meanies_update(
+/// System that keeps meanie sprites and animation frames in sync with the simulated meanies
+#[allow(clippy::type_complexity)]
+pub fn meanies_sync(
     mut commands: Commands,
-    mut meanies: Query<(Entity, &mut Meanie, &mut Transform, &Animation)>,
-    mut bullets: Query<(Entity, &Transform, &Bullet), Without<Meanie>>,
-    mut balls: Query<(&Transform, &mut Ball), Without<Meanie>>,
-    paddle_comp: Single<(&Transform, &Paddle), Without<Meanie>>,
-    mut game: ResMut<Game>,
+    mut meanies: Query<(Entity, &mut Transform, &mut Sprite, &Meanie)>,
+    mut shadows: Query<(&ChildOf, &mut Sprite), (With<MeanieShadow>, Without<Meanie>)>,
+    mut portals: Query<(&mut Animation, &mut MeaniePortal)>,
+    sim: Res<Simulation>,
+    game: Res<Game>,
+    fixed_time: Res<Time<Fixed>>,
 ) {
-    let dt = game.avg_delta;
-    let (p_tr, paddle) = paddle_comp.into_inner();
-    let p_size = PADDLE_SIZES[paddle.variant];
-
-    // Move meanies
-    'meanie_loop: for (entity, mut meanie, mut tr, anim) in &mut meanies {
-        if tr.translation.y > MEANIES_MAXY {
-            tr.translation.y -= dt * meanie.speed;
-        } else {
-            // Meanie follows a random walk down the screen
-            meanie.angle += rand::rng().random_range(-PI / 16.0..=PI / 16.0);
-            if meanie.angle < MEANIES_MIN_ANGLE {
-                meanie.angle = 2. * MEANIES_MIN_ANGLE - meanie.angle;
-            } else if meanie.angle > MEANIES_MAX_ANGLE {
-                meanie.angle = 2. * MEANIES_MAX_ANGLE - meanie.angle;
-            }
-
-            // Adjust angle if meanie is in collision
-
-            let direction = Vec2::from_angle(meanie.angle);
-
-            // meanies are slow so we don't need to animate pixel by pixel
-            tr.translation.y += dt * direction.y * meanie.speed;
-            tr.translation.x += dt * direction.x * meanie.speed;
-            if tr.translation.x < MEANIES_MINX {
-                tr.translation.x = 2. * MEANIES_MINX - tr.translation.x;
-                meanie.angle = PI - meanie.angle;
-            } else if tr.translation.x > MEANIES_MAXX {
-                tr.translation.x = 2. * MEANIES_MAXX - tr.translation.x;
-                meanie.angle = -PI - meanie.angle;
-            }
-
-            // Bottom border
-            if tr.translation.y < MEANIES_MINY {
-                commands.entity(entity).despawn();
-                game.nmeanies -= 1;
-                continue 'meanie_loop;
+    // Interpolate between the last two simulation steps
+    let alpha = fixed_time.overstep_fraction();
+    for (meanie_entity, mut meanie_tr, mut sprite, meanie) in &mut meanies {
+        match sim.meanies.iter().find(|m| m.id == meanie.id) {
+            Some(sim_meanie) => {
+                let pos = sim_meanie.prev_pos.lerp(sim_meanie.pos, alpha);
+                meanie_tr.translation.x = pos.x;
+                meanie_tr.translation.y = pos.y;
+                sprite.image = game.h_meanies_frames[sim_meanie.variant][sim_meanie.frame()].clone();
             }
+            None => commands.entity(meanie_entity).despawn(),
         }
+    }
 
-        // Meanie size and position
-        let variant = meanie.variant;
-        let frame = anim.current_frame;
-        let meanie_size = MEANIES_SIZES[variant][frame];
-        let meanie_tr = tr.translation.truncate();
-
-        // Check collision with paddle
-        let body = Body::Rectangular(p_tr.translation.truncate(), p_size);
-        if let Some(_collides) = collision(body, meanie_tr, meanie_size) {
-            meanie_destroy(&mut commands, &tr, entity, &mut game);
-            continue 'meanie_loop;
-        }
-
-        // Check collision with bullet
-        for (bullet_entity, bullet_tr, _) in &mut bullets {
-            let body = Body::Rectangular(bullet_tr.translation.truncate(), BULLET_SIZE);
-            if let Some(_collides) = collision(body, meanie_tr, meanie_size) {
-                meanie_destroy(&mut commands, &tr, entity, &mut game);
-                commands.entity(bullet_entity).despawn();
-                commands.spawn((
-                    AudioPlayer::new(
-                        game.sound_bullet_hit
-                            .choose(&mut rand::rng())
-                            .unwrap()
-                            .clone(),
-                    ),
-                    PlaybackSettings::DESPAWN,
-                ));
-                continue 'meanie_loop;
-            }
+    // Shadows show the same frame as their meanie
+    for (parent, mut sprite) in &mut shadows {
+        let Ok((_, _, _, meanie)) = meanies.get(parent.parent()) else {
+            continue;
+        };
+        if let Some(sim_meanie) = sim.meanies.iter().find(|m| m.id == meanie.id) {
+            sprite.image = game.h_meanies_shadows[sim_meanie.variant][sim_meanie.frame()].clone();
         }
+    }
 
-        // Check collision with balls
-        for (ball_tr, mut ball) in &mut balls {
-            // Skip balls caught by the paddle
-            if ball.caught {
-                continue;
-            }
-            let body = Body::Round(ball_tr.translation.truncate(), BALL_RADIUS);
-            if let Some(_collides) = collision(body, meanie_tr, meanie_size) {
-                meanie_destroy(&mut commands, &tr, entity, &mut game);
-                ball.direction.y *= -1.;
-                continue 'meanie_loop;
-            }
+    for sim_meanie in &sim.meanies {
+        if meanies.iter().any(|(_, _, _, m)| m.id == sim_meanie.id) {
+            continue;
         }
+        commands.spawn((
+            Sprite::from_image(game.h_meanies_frames[sim_meanie.variant][0].clone()),
+            Transform::from_xyz(sim_meanie.pos.x, sim_meanie.pos.y, LAYER_MEANIES),
+            Meanie { id: sim_meanie.id },
+            GameTag,
+            children![(
+                Sprite::from_image(game.h_meanies_shadows[sim_meanie.variant][0].clone()),
+                Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS - LAYER_MEANIES),
+                MeanieShadow,
+            )],
+        ));
     }
 
-    // Spawn new meanie
-    if game.nmeanies < MEANIES_MAX && secret_is_unlocked(Secret::Meanies as usize, &game) {
-        let spawn_prob: f32 = MEANIES_PER_SECOND * dt;
-        if rand::rng().random::<f32>() < spawn_prob {
-            let variant: usize = rand::rng().random_range(0..MEANIES_TYPES);
-            let portal: usize = rand::rng().random_range(0..2);
-            let _new_meanie = commands
-                .spawn((
-                    Sprite::from_image(game.h_meanies_frames[variant][0].clone()),
-                    Transform {
-                        translation: Vec3::new(
-                            MEANIES_PORTAL_X[portal],
-                            MEANIES_PORTAL_Y,
-                            LAYER_MEANIES,
-                        ),
-                        ..default()
-                    },
-                    Animation {
-                        timer: Timer::from_seconds(1.0 / MEANIES_FRAMERATE, TimerMode::Repeating),
-                        frozen: false,
-                        variant: AnimationType::Repeat,
-                        frames: game.h_meanies_frames[variant].clone(),
-                        current_frame: 0,
-                        reverse: false,
-                        velocity: Vec2::new(0., 0.),
-                    },
-                    Meanie {
-                        variant,
-                        speed: MEANIES_SPEED,
-                        angle: -FRAC_PI_2,
-                    },
-                    GameTag,
-                ))
-                .id();
-
-            // let meanie_shadow = commands
-            //     .spawn((
-            //         Sprite::from_image(game.h_meanies_shadows[variant].clone()),
-            //         Transform::from_xyz(SHADOW_DX, SHADOW_DY, LAYER_SHADOWS),
-            //     ))
-            //     .id();
-            // commands.entity(new_ball).add_child(ball_shadow);
-
-            game.nmeanies += 1;
+    // Doors stay open while a meanie is still inside them
+    for (mut anim, mut portal) in &mut portals {
+        let x = MEANIES_PORTAL_X[portal.idx];
+        let open = sim.meanies.iter().any(|m| {
+            (m.pos.x - x).abs() < MEANIES_PORTAL_DOOR_SIZE.x / 2.
+                && m.pos.y > MEANIES_PORTAL_DOOR_Y - MEANIES_PORTAL_DOOR_SIZE.y / 2.
+        });
+        if open != portal.open {
+            portal.open = open;
+            anim.frozen = false;
+            anim.reverse = !open;
         }
     }
 }
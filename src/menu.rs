@@ -1,9 +1,15 @@
+use crate::audio::*;
 use crate::consts::*;
+use crate::controls::*;
 use crate::game::*;
+use crate::highscores::*;
 use crate::save::*;
+use crate::settings::*;
 use crate::shop::*;
 
 use bevy::app::AppExit;
+use bevy::input::ButtonState;
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
 
 use super::{GameState, despawn_all};
@@ -11,11 +17,34 @@ use super::{GameState, despawn_all};
 enum MenuState {
     Menu,
     Credits,
+    HighScores,
     Reset,
+    Controls,
+    Options,
+    Profiles,
+    Handle,
 }
 
 #[derive(Component)]
-struct Menu(MenuState);
+struct Menu {
+    state: MenuState,
+    /// Entry highlighted for gamepads (see `get_menu_entries`)
+    selected: usize,
+    /// Entry highlighted in the controls screen (index in `ACTIONS`)
+    action: usize,
+    /// Waiting for the new key of the highlighted action
+    rebinding: bool,
+    /// Entry highlighted in the options screen
+    option: usize,
+    /// Profiles listed in the profiles screen
+    profiles: Vec<String>,
+    /// Entry highlighted in the profiles screen (see `get_profiles_text`)
+    profile: usize,
+    /// Name being typed for a new profile
+    new_profile: Option<String>,
+    /// Handle being typed on the first run
+    handle: String,
+}
 
 #[derive(Component)]
 struct MenuTag;
@@ -26,116 +55,474 @@ pub fn menu_plugin(app: &mut App) {
         .add_systems(Update, menu_update.run_if(in_state(GameState::Menu)));
 }
 
-fn menu_enter(mut commands: Commands, game: Res<Game>) {
+fn menu_enter(
+    mut commands: Commands,
+    game: Res<Game>,
+    controls: Res<Controls>,
+    mut audio: Audio,
+) {
+    // Players choose a handle on the first run, before the menu is shown
+    let first_run = game.handle.is_empty();
+
     // Background
-    commands.spawn((Sprite::from_image(game.h_menu_bg.clone()), MenuTag));
+    commands.spawn((
+        Sprite::from_image(game.h_menu_bg.clone()),
+        if first_run {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        },
+        MenuTag,
+    ));
 
     // Menu Text Placeholder
     commands.spawn((
-        Text2d::new(get_menu_text(&game)),
+        Text2d::new(if first_run {
+            get_handle_text("", &controls)
+        } else {
+            get_menu_text(&game, &controls, 0)
+        }),
         TextFont {
             font_size: 20.0,
             ..default()
         },
         TextLayout::new_with_justify(Justify::Left),
-        Menu(MenuState::Menu),
+        Menu {
+            state: if first_run {
+                MenuState::Handle
+            } else {
+                MenuState::Menu
+            },
+            selected: 0,
+            action: 0,
+            rebinding: false,
+            option: 0,
+            profiles: Vec::new(),
+            profile: 0,
+            new_profile: None,
+            handle: String::new(),
+        },
         MenuTag,
     ));
 
     // Music
     if !secret_is_unlocked(Secret::Arkanoid as usize, &game) {
-        commands.spawn((
-            AudioPlayer::new(game.music_main_theme.clone()),
-            PlaybackSettings::LOOP,
-            MenuTag,
-        ));
+        audio.music(&game.music_main_theme, 1.).insert(MenuTag);
     } else {
-        commands.spawn((
-            AudioPlayer::new(game.music_arkanoid.clone()),
-            PlaybackSettings::LOOP,
-            MenuTag,
-        ));
+        audio.music(&game.music_arkanoid, 1.).insert(MenuTag);
     }
 }
 
-fn get_menu_text(game: &Game) -> String {
+// Menu entries with the action that selects them
+fn get_menu_entries(game: &Game) -> Vec<(Action, &'static str)> {
+    let mut entries = vec![
+        (Action::Play, "PLAY"),
+        (Action::Shop, "SHOP"),
+        (Action::Reset, "RESET PROGRESS"),
+    ];
     if secret_is_unlocked(Secret::Credits as usize, game) {
-        "1 - PLAY\n\n2 - SHOP\n\n3 - RESET PROGRESS\n\n4 - CREDITS\n\nESC - Exit".to_string()
+        entries.push((Action::Credits, "CREDITS"));
+    }
+    entries.push((Action::HighScores, "HIGH SCORES"));
+    entries.push((Action::Editor, "LEVEL EDITOR"));
+    entries.push((Action::Profiles, "PROFILES"));
+    entries.push((Action::Controls, "CONTROLS"));
+    entries.push((Action::Options, "OPTIONS"));
+    entries.push((Action::Back, "Exit"));
+    entries
+}
+
+fn get_menu_text(game: &Game, controls: &Controls, selected: usize) -> String {
+    let entries = get_menu_entries(game)
+        .iter()
+        .enumerate()
+        .map(|(idx, (action, entry))| {
+            let marker = if idx == selected { "> " } else { "  " };
+            format!("{marker}{} - {entry}", controls.key_name(*action))
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    format!("PLAYER: {} ({})\n\n{entries}", game.handle, profile())
+}
+
+fn get_handle_text(handle: &str, controls: &Controls) -> String {
+    format!(
+        "WELCOME!\n\nChoose your player name:\n{handle}_\n\nPress {} to confirm",
+        controls.key_name(Action::Confirm)
+    )
+}
+
+// Adds the letters and digits typed to a name, up to `len` characters
+fn name_input(keyboard: &mut MessageReader<KeyboardInput>, name: &mut String, len: usize) {
+    for ev in keyboard.read() {
+        if ev.state == ButtonState::Released {
+            continue;
+        }
+        if let Key::Character(input) = &ev.logical_key {
+            for c in input.chars() {
+                if c.is_ascii_alphanumeric() && name.len() < len {
+                    name.push(c.to_ascii_uppercase());
+                }
+            }
+        }
+    }
+}
+
+// Existing profiles, followed by an entry to create a new one
+fn get_profiles_text(
+    profiles: &[String],
+    controls: &Controls,
+    selected: usize,
+    new_profile: &Option<String>,
+) -> String {
+    if let Some(name) = new_profile {
+        return format!(
+            "NEW PROFILE\n\nEnter a name:\n{name}_\n\nPress {} to confirm, {} to cancel",
+            controls.key_name(Action::Confirm),
+            controls.key_name(Action::Back)
+        );
+    }
+    let current = profile();
+    let mut text = String::from("PROFILES\n\n");
+    for (idx, name) in profiles.iter().enumerate() {
+        let marker = if idx == selected { "> " } else { "  " };
+        let playing = if *name == current { " (PLAYING)" } else { "" };
+        text.push_str(&format!("{marker}{name}{playing}\n\n"));
+    }
+    let marker = if selected == profiles.len() {
+        "> "
     } else {
-        "1 - PLAY\n\n2 - SHOP\n\n3 - RESET PROGRESS\n\nESC - Exit".to_string()
+        "  "
+    };
+    text.push_str(&format!(
+        "{marker}NEW PROFILE\n\n{} to play with a profile, {} to go BACK",
+        controls.key_name(Action::Confirm),
+        controls.key_name(Action::Back)
+    ));
+    text
+}
+
+fn get_controls_text(controls: &Controls, selected: usize, rebinding: bool) -> String {
+    let mut text = String::from("CONTROLS\n\n");
+    let first = selected
+        .saturating_sub(CONTROLS_ROWS / 2)
+        .min(ACTIONS.len() - CONTROLS_ROWS);
+    let rows = ACTIONS.iter().enumerate().skip(first).take(CONTROLS_ROWS);
+    for (idx, action) in rows {
+        let marker = if idx == selected { "> " } else { "  " };
+        let key = if idx == selected && rebinding {
+            "...".to_string()
+        } else {
+            controls.key_name(*action)
+        };
+        text.push_str(&format!("{marker}{:<22} {key}\n", action.title()));
+    }
+    if rebinding {
+        text.push_str("\nPress the new key (ESC to cancel)");
+    } else {
+        text.push_str(&format!(
+            "\n{} to change a key, {} to go BACK",
+            controls.key_name(Action::Confirm),
+            controls.key_name(Action::Back)
+        ));
     }
+    text
 }
 
+#[allow(clippy::too_many_arguments)]
 fn menu_update(
     mut game_state: ResMut<NextState<GameState>>,
-    input: Res<ButtonInput<KeyCode>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut controls: ResMut<Controls>,
+    mut settings: ResMut<Settings>,
     menu_comp: Single<(&mut Text2d, &mut Menu), With<MenuTag>>,
     bg_comp: Single<(&Sprite, &mut Visibility), With<MenuTag>>,
     mut exit: MessageWriter<AppExit>,
     mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    highscores: Res<HighScores>,
+    mode: Res<GameMode>,
+    mut keyboard: MessageReader<KeyboardInput>,
+    mut audio: Audio,
 ) {
     let (mut text, mut menu) = menu_comp.into_inner();
     let (_, mut visibility) = bg_comp.into_inner();
 
-    match menu.0 {
+    // Wait for the new key of an action
+    if menu.rebinding {
+        if let Some(key) = keys.get_just_pressed().next() {
+            if *key != KeyCode::Escape {
+                match controls.rebind(ACTIONS[menu.action], *key) {
+                    Ok(()) => controls.save(),
+                    Err(other) => {
+                        // Keep waiting for a key that is free
+                        *text = Text2d::new(format!(
+                            "{}\n{} is already used by {other}",
+                            get_controls_text(&controls, menu.action, true),
+                            key_name(*key),
+                        ));
+                        return;
+                    }
+                }
+            }
+            menu.rebinding = false;
+            *text = Text2d::new(get_controls_text(&controls, menu.action, false));
+        }
+        return;
+    }
+
+    // The highlighted entry is selected with Confirm, as if its key was pressed
+    let entries = get_menu_entries(&game);
+    let just_pressed = |action: Action| controls.just_pressed(action, &keys, &gamepads);
+    let confirm = just_pressed(Action::Confirm);
+    let confirmed = match menu.state {
+        MenuState::Menu if confirm => Some(entries[menu.selected].0),
+        _ => None,
+    };
+    let pressed = |action: Action| just_pressed(action) || confirmed == Some(action);
+
+    match menu.state {
         MenuState::Menu => {
-            if input.just_pressed(KeyCode::Digit1) {
+            // Move highlighted entry
+            let mut selected = menu.selected;
+            if just_pressed(Action::Up) {
+                selected = (selected + entries.len() - 1) % entries.len();
+            }
+            if just_pressed(Action::Down) {
+                selected = (selected + 1) % entries.len();
+            }
+
+            if pressed(Action::Play) {
                 // Start new game
                 game.current_level = 0;
+                rng.reset();
                 game_state.set(GameState::Transition);
-            } else if input.just_pressed(KeyCode::Digit2) {
+            } else if pressed(Action::Shop) {
                 // Enter Shop
                 game_state.set(GameState::Shop);
-            } else if input.just_pressed(KeyCode::Digit3) {
+            } else if pressed(Action::Reset) {
                 // Delete all progress
-                menu.0 = MenuState::Reset;
-                *text = Text2d::new(
+                menu.state = MenuState::Reset;
+                *text = Text2d::new(format!(
                     "Delete all game progress \n\
-		     and secrets? (Y/N)\n\n\
-		     THE GAME WILL CLOSE"
-                        .to_string(),
-                );
-            } else if input.just_pressed(KeyCode::Digit4)
+		     and secrets? ({}/{})\n\n\
+		     THE GAME WILL CLOSE",
+                    controls.key_name(Action::Yes),
+                    controls.key_name(Action::No)
+                ));
+            } else if pressed(Action::Credits)
                 && secret_is_unlocked(Secret::Credits as usize, &game)
             {
                 // Credits screen
                 *visibility = Visibility::Hidden;
-                menu.0 = MenuState::Credits;
-                if game.levels_unlocked.iter().any(|s| s.is_empty()) {
+                menu.state = MenuState::Credits;
+                let back = controls.key_name(Action::Back);
+                let levels_unlocked = &game.levels_unlocked[..game.nlevels];
+                if levels_unlocked.iter().any(|s| s.is_empty()) {
                     *text = Text2d::new(format!(
                         "{CREDITS}\n\nPermanently unlock all portals to claim a phonetool icon.\n\n\
-			 Press ESC to go BACK"
+			 Press {back} to go BACK"
                     ));
                 } else {
+                    let codes: Vec<String> =
+                        levels_unlocked.chunks(4).map(|c| c.join(" ")).collect();
                     *text = Text2d::new(format!(
                         "{CREDITS}\n\nClaim a phonetool icon with these codes:\n\
-			 {}\n{}\n\n\
-			 Press ESC to go BACK",
-                        game.levels_unlocked[0..4].join(" "),
-                        game.levels_unlocked[4..NLEVELS].join(" ")
+			 {}\n\n\
+			 Press {back} to go BACK",
+                        codes.join("\n")
                     ));
                 }
-            } else if input.just_pressed(KeyCode::Escape) {
+            } else if pressed(Action::HighScores) {
+                // High scores screen
+                *visibility = Visibility::Hidden;
+                menu.state = MenuState::HighScores;
+                // The table of the seed given with -s, if any
+                let mut tables = highscores.format(GameMode::Normal);
+                if *mode != GameMode::Normal {
+                    tables = format!("{tables}\n\n{}", highscores.format(*mode));
+                }
+                *text = Text2d::new(format!(
+                    "{tables}\n\nPress {} to go BACK",
+                    controls.key_name(Action::Back)
+                ));
+            } else if pressed(Action::Editor) {
+                // Level editor
+                game_state.set(GameState::Editor);
+            } else if pressed(Action::Profiles) {
+                // Profiles screen, starting at the current profile
+                *visibility = Visibility::Hidden;
+                menu.state = MenuState::Profiles;
+                menu.profiles = profiles();
+                menu.profile = menu
+                    .profiles
+                    .iter()
+                    .position(|p| *p == profile())
+                    .unwrap_or(0);
+                menu.new_profile = None;
+                *text = Text2d::new(get_profiles_text(
+                    &menu.profiles,
+                    &controls,
+                    menu.profile,
+                    &None,
+                ));
+            } else if pressed(Action::Controls) {
+                // Controls screen
+                *visibility = Visibility::Hidden;
+                menu.state = MenuState::Controls;
+                menu.action = 0;
+                *text = Text2d::new(get_controls_text(&controls, 0, false));
+            } else if pressed(Action::Options) {
+                // Options screen
+                *visibility = Visibility::Hidden;
+                menu.state = MenuState::Options;
+                menu.option = 0;
+                *text = Text2d::new(get_options_text(&settings, &controls, 0));
+            } else if pressed(Action::Back) {
                 exit.write(AppExit::Success);
+            } else if selected != menu.selected {
+                audio.play(AudioBus::Ui, &game.sound_menu);
+                menu.selected = selected;
+                *text = Text2d::new(get_menu_text(&game, &controls, selected));
             }
         }
         MenuState::Reset => {
             // Reset confirmation
-            if input.just_pressed(KeyCode::KeyN) || input.just_pressed(KeyCode::Escape) {
-                *text = Text2d::new(get_menu_text(&game));
-                menu.0 = MenuState::Menu;
-            } else if input.just_pressed(KeyCode::KeyY) {
+            if pressed(Action::No) || pressed(Action::Back) {
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            } else if pressed(Action::Yes) || confirm {
                 game_reset();
-                *text = Text2d::new(get_menu_text(&game));
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
                 exit.write(AppExit::Success);
             }
         }
-        MenuState::Credits => {
-            // Credits Screen
-            if input.just_pressed(KeyCode::Escape) {
+        MenuState::Credits | MenuState::HighScores => {
+            // Credits or High Scores Screen
+            if pressed(Action::Back) || confirm {
+                *visibility = Visibility::Visible;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            }
+        }
+        MenuState::Controls => {
+            // Controls screen, Confirm waits for a new key
+            let mut action = menu.action;
+            if just_pressed(Action::Up) {
+                action = (action + ACTIONS.len() - 1) % ACTIONS.len();
+            }
+            if just_pressed(Action::Down) {
+                action = (action + 1) % ACTIONS.len();
+            }
+
+            if pressed(Action::Back) {
+                *visibility = Visibility::Visible;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            } else if confirm {
+                menu.rebinding = true;
+                *text = Text2d::new(get_controls_text(&controls, action, true));
+            } else if action != menu.action {
+                menu.action = action;
+                *text = Text2d::new(get_controls_text(&controls, action, false));
+            }
+        }
+        MenuState::Profiles => {
+            // Profiles screen, the game is loaded again for another profile
+            if let Some(mut name) = menu.new_profile.take() {
+                if just_pressed(Action::Confirm)
+                    && let Some(name) = profile_name(&name)
+                {
+                    profile_select(&name);
+                    game_state.set(GameState::Splash);
+                    return;
+                }
+                if !just_pressed(Action::Back) {
+                    if just_pressed(Action::Delete) {
+                        name.pop();
+                    }
+                    name_input(&mut keyboard, &mut name, PROFILE_NAME_LEN);
+                    menu.new_profile = Some(name);
+                }
+                *text = Text2d::new(get_profiles_text(
+                    &menu.profiles,
+                    &controls,
+                    menu.profile,
+                    &menu.new_profile,
+                ));
+                return;
+            }
+
+            // The last entry creates a new profile
+            let nentries = menu.profiles.len() + 1;
+            let mut selected = menu.profile;
+            if just_pressed(Action::Up) {
+                selected = (selected + nentries - 1) % nentries;
+            }
+            if just_pressed(Action::Down) {
+                selected = (selected + 1) % nentries;
+            }
+
+            if pressed(Action::Back) {
+                *visibility = Visibility::Visible;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            } else if confirm && selected == nentries - 1 {
+                // Ignore the keys pressed before the name is typed
+                keyboard.clear();
+                menu.new_profile = Some(String::new());
+                *text = Text2d::new(get_profiles_text(
+                    &menu.profiles,
+                    &controls,
+                    selected,
+                    &menu.new_profile,
+                ));
+            } else if confirm && menu.profiles[selected] != profile() {
+                profile_select(&menu.profiles[selected]);
+                game_state.set(GameState::Splash);
+            } else if confirm {
+                *visibility = Visibility::Visible;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            } else if selected != menu.profile {
+                audio.play(AudioBus::Ui, &game.sound_menu);
+                menu.profile = selected;
+                *text = Text2d::new(get_profiles_text(
+                    &menu.profiles,
+                    &controls,
+                    selected,
+                    &None,
+                ));
+            }
+        }
+        MenuState::Handle => {
+            // First run, the handle is kept in the profile
+            if confirm && !menu.handle.is_empty() {
+                game.handle = menu.handle.clone();
+                let handle = menu.handle.clone();
+                save_update(|save| save.handle = handle);
+                *visibility = Visibility::Visible;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+                return;
+            }
+            if just_pressed(Action::Delete) {
+                menu.handle.pop();
+            }
+            name_input(&mut keyboard, &mut menu.handle, PLAYER_HANDLE_LEN);
+            *text = Text2d::new(get_handle_text(&menu.handle, &controls));
+        }
+        MenuState::Options => {
+            // Options screen, saved when leaving it
+            let mut option = menu.option;
+            if options_update(just_pressed, &mut settings, &mut option) {
                 *visibility = Visibility::Visible;
-                *text = Text2d::new(get_menu_text(&game));
-                menu.0 = MenuState::Menu;
+                *text = Text2d::new(get_menu_text(&game, &controls, menu.selected));
+                menu.state = MenuState::Menu;
+            } else if settings.is_changed() || option != menu.option {
+                menu.option = option;
+                *text = Text2d::new(get_options_text(&settings, &controls, option));
             }
         }
     }
@@ -1,115 +1,106 @@
-use crate::balls_and_bullets::*;
 use crate::consts::*;
+use crate::controls::*;
 use crate::game::*;
-use crate::*;
-use bevy::audio::*;
+use crate::sim::*;
 use bevy::prelude::*;
-use std::time::Duration;
+use bevy::window::PrimaryWindow;
 
 #[derive(Component)]
 /// Component for the player-controlled paddle
 pub struct Paddle {
-    /// Current paddle variant/type (affects abilities)
+    /// Paddle variant/type currently shown
     pub variant: usize,
-    /// Timer for gun paddle shooting cooldown
-    pub gun_timer: Timer,
 }
 
 #[derive(Component)]
 /// Component for the paddle's shadow visual effect
 pub struct PaddleShadow {}
 
-/// System that handles paddle movement and control
-/// Processes keyboard input to move the paddle and fire bullets
-pub fn paddle_update(
-    mut commands: Commands,
-    input: Res<ButtonInput<KeyCode>>,
-    paddle_comp: Single<(&mut Transform, &mut Paddle)>,
-    mut ball_query: Query<(&mut Transform, &mut Ball), Without<Paddle>>,
-    mut game: ResMut<Game>,
-    mut game_state: ResMut<NextState<GameState>>,
-) {
-    let (mut paddle_tr, mut paddle) = paddle_comp.into_inner();
-    let dt = game.avg_delta;
+#[derive(Resource, Default)]
+/// Mouse state for the paddle, when it follows the cursor
+pub struct PaddleMouse {
+    /// Whether the paddle follows the cursor (-m)
+    pub enabled: bool,
+    /// Cursor position in world coordinates (None if outside the window)
+    pub x: Option<f32>,
+    /// Whether any mouse button is pressed
+    pub pressed: bool,
+}
 
+/// Reads the keyboard and gamepads into the input for the next simulation step
+pub fn paddle_input(actions: &Actions, mouse: &PaddleMouse) -> SimInput {
     // Paddle direction
     let mut dir = 0.0;
-    if input.pressed(KeyCode::ArrowLeft) {
+    let mut throttle = 0.0;
+    if actions.pressed(Action::MoveLeft) {
         dir -= 1.0;
     }
-    if input.pressed(KeyCode::ArrowRight) {
+    if actions.pressed(Action::MoveRight) {
         dir += 1.0;
     }
+    let boost = actions.pressed(Action::Boost);
+    let mut fire = actions.pressed(Action::Fire);
 
-    // Speed
-    let paddle_speed = if input.pressed(KeyCode::ShiftLeft) {
-        PADDLE_MAX_SPEED
-    } else {
-        PADDLE_MIN_SPEED
-    };
-
-    // Move paddle
-    let mut delta_x = 0.0;
-    if dir != 0.0 {
-        let mut new_x = paddle_tr.translation.x + dir * paddle_speed * dt;
-        let min_x = BALLAREA_MINX + PADDLE_SIZES[paddle.variant].x / 2.0;
-        let mut max_x = BALLAREA_MAXX - PADDLE_SIZES[paddle.variant].x / 2.0;
-
-        // Change xmax and possibly level if portal is open
-        if game.portal_open {
-            max_x += 20.;
-            if new_x > max_x {
-                game.current_level += 1;
-                game_state.set(GameState::Transition);
-                return;
-            }
+    // Gamepad stick speed grows with the tilt
+    for gamepad in &actions.gamepads {
+        let x = gamepad.left_stick().x;
+        if x.abs() > GAMEPAD_DEADZONE {
+            dir = x.signum();
+            throttle = ((x.abs() - GAMEPAD_DEADZONE) / (1.0 - GAMEPAD_DEADZONE)).min(1.0);
         }
-
-        new_x = new_x.clamp(min_x, max_x);
-        delta_x = new_x - paddle_tr.translation.x;
-        paddle_tr.translation.x = new_x;
     }
 
-    // Move and/or release catched balls
-    let uncatch = input.pressed(KeyCode::Space);
-    for (mut transform, mut ball) in &mut ball_query {
-        if ball.caught {
-            transform.translation.x += delta_x;
-            ball.caught = !uncatch;
-        }
+    // Mouse: the paddle follows the cursor and any button fires
+    let mut target_x = None;
+    if mouse.enabled {
+        target_x = mouse.x;
+        fire |= mouse.pressed;
     }
 
-    // Fire Gun
-    paddle.gun_timer.tick(Duration::from_secs_f32(dt));
-    if paddle.variant == PADDLE_GUN && input.pressed(KeyCode::Space) && paddle.gun_timer.is_finished()
-    {
-        let mut left_bullet_tr: Transform = *paddle_tr;
-        left_bullet_tr.translation.x += GUN_LEFT_X;
-        left_bullet_tr.translation.y += GUN_Y;
-        left_bullet_tr.translation.z = LAYER_GUN;
-        commands.spawn((
-            Sprite::from_image(game.h_bullet.clone()),
-            left_bullet_tr,
-            Bullet,
-            GameTag,
-        ));
-        commands.spawn((
-            AudioPlayer::new(game.sound_fire_bullet.clone()),
-            PlaybackSettings::DESPAWN,
-        ));
+    SimInput {
+        dir: dir.clamp(-1.0, 1.0),
+        boost,
+        throttle,
+        target_x,
+        fire,
+    }
+}
 
-        let mut right_bullet_tr: Transform = *paddle_tr;
-        right_bullet_tr.translation.x += GUN_RIGHT_X;
-        right_bullet_tr.translation.y += GUN_Y;
-        right_bullet_tr.translation.z = LAYER_GUN;
-        commands.spawn((
-            Sprite::from_image(game.h_bullet.clone()),
-            right_bullet_tr,
-            Bullet,
-            GameTag,
-        ));
-        paddle.gun_timer.reset()
+/// System that reads the cursor position and buttons for the paddle
+pub fn paddle_mouse(
+    mut mouse: ResMut<PaddleMouse>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera_comp: Single<(&Camera, &GlobalTransform)>,
+) {
+    if !mouse.enabled {
+        return;
     }
+    let (camera, camera_tr) = camera_comp.into_inner();
+    mouse.x = window
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world_2d(camera_tr, pos).ok())
+        .map(|pos| pos.x);
+    mouse.pressed = buttons.get_pressed().next().is_some();
 }
 
+/// System that keeps the paddle sprite in sync with the simulated paddle
+pub fn paddle_sync(
+    paddle_comp: Single<(&mut Transform, &mut Sprite, &mut Paddle), Without<PaddleShadow>>,
+    shadow_comp: Single<&mut Sprite, With<PaddleShadow>>,
+    sim: Res<Simulation>,
+    game: Res<Game>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let (mut paddle_tr, mut sprite, mut paddle) = paddle_comp.into_inner();
+    let alpha = fixed_time.overstep_fraction();
+    paddle_tr.translation.x = sim.paddle.prev_x.lerp(sim.paddle.x, alpha);
 
+    // Update paddle variant
+    if paddle.variant != sim.paddle.variant {
+        let mut sprite_shadow = shadow_comp.into_inner();
+        paddle.variant = sim.paddle.variant;
+        *sprite = Sprite::from_image(game.h_paddles[paddle.variant].clone());
+        *sprite_shadow = Sprite::from_image(game.h_paddle_shadows[paddle.variant].clone());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::audio::*;
+use crate::consts::*;
+use crate::controls::*;
+use crate::game::*;
+use crate::replay::*;
+use crate::settings::*;
+
+use bevy::prelude::*;
+
+use super::{GameState, PauseState, despawn_all};
+
+/// Entries of the pause menu, in order
+const PAUSE_ENTRIES: [&str; 4] = ["RESUME", "RESTART LEVEL", "SETTINGS", "QUIT TO MENU"];
+
+#[derive(Component)]
+struct PauseMenu {
+    /// Entry highlighted (index in `PAUSE_ENTRIES`)
+    selected: usize,
+    /// Whether the options screen is shown instead of the entries
+    settings: bool,
+    /// Entry highlighted in the options screen
+    option: usize,
+}
+
+#[derive(Component)]
+struct PauseTag;
+
+pub fn pause_plugin(app: &mut App) {
+    app.add_systems(OnEnter(PauseState::Paused), pause_enter)
+        .add_systems(
+            OnExit(PauseState::Paused),
+            (pause_exit, despawn_all::<PauseTag>),
+        )
+        .add_systems(Update, pause_start.run_if(in_state(PauseState::Running)))
+        .add_systems(Update, pause_update.run_if(in_state(PauseState::Paused)));
+}
+
+fn get_pause_text(game: &Game, selected: usize) -> String {
+    let mut text = String::from("PAUSED\n\n");
+    for (idx, entry) in PAUSE_ENTRIES.iter().enumerate() {
+        let marker = if idx == selected { "> " } else { "  " };
+        // Play-tested levels go back to the editor instead
+        let entry = match *entry {
+            "QUIT TO MENU" if game.playtest => "QUIT TO EDITOR",
+            _ => entry,
+        };
+        text.push_str(&format!("{marker}{entry}\n\n"));
+    }
+    text
+}
+
+/// Pauses the level when the player presses Pause. Back is not read here,
+/// as its gamepad buttons also fire
+fn pause_start(actions: Actions, mut pause_state: ResMut<NextState<PauseState>>) {
+    if actions.just_pressed(Action::Pause) {
+        pause_state.set(PauseState::Paused);
+    }
+}
+
+/// Freezes the level clock and sounds, and shows the pause menu over the level
+fn pause_enter(
+    mut commands: Commands,
+    mut time: ResMut<Time<Virtual>>,
+    sinks: Query<&AudioSink>,
+    spatial_sinks: Query<&SpatialAudioSink>,
+    game: Res<Game>,
+) {
+    // Timers, animations and fixed steps all run on virtual time
+    time.pause();
+    for sink in &sinks {
+        sink.pause();
+    }
+    for sink in &spatial_sinks {
+        sink.pause();
+    }
+
+    commands.spawn((
+        Sprite::from_color(
+            Color::srgba(0., 0., 0., 0.7),
+            Vec2::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+        ),
+        Transform::from_xyz(0., 0., LAYER_OVERLAY),
+        PauseTag,
+    ));
+    commands.spawn((
+        Text2d::new(get_pause_text(&game, 0)),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextLayout::new_with_justify(Justify::Left),
+        Transform::from_xyz(0., 0., LAYER_OVERLAY + 1.),
+        PauseMenu {
+            selected: 0,
+            settings: false,
+            option: 0,
+        },
+        PauseTag,
+    ));
+}
+
+fn pause_exit(
+    mut time: ResMut<Time<Virtual>>,
+    sinks: Query<&AudioSink>,
+    spatial_sinks: Query<&SpatialAudioSink>,
+) {
+    time.unpause();
+    for sink in &sinks {
+        sink.play();
+    }
+    for sink in &spatial_sinks {
+        sink.play();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_update(
+    mut commands: Commands,
+    actions: Actions,
+    menu_comp: Single<(&mut Text2d, &mut PauseMenu)>,
+    mut pause_state: ResMut<NextState<PauseState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut game: ResMut<Game>,
+    recorder: Res<ReplayRecorder>,
+    player: Option<ResMut<ReplayPlayer>>,
+    mut settings: ResMut<Settings>,
+    mut audio: Audio,
+) {
+    let (mut text, mut menu) = menu_comp.into_inner();
+
+    // Options screen, saved when leaving it
+    if menu.settings {
+        let mut option = menu.option;
+        if options_update(
+            |action| actions.just_pressed(action),
+            &mut settings,
+            &mut option,
+        ) {
+            menu.settings = false;
+            *text = Text2d::new(get_pause_text(&game, menu.selected));
+        } else if settings.is_changed() || option != menu.option {
+            menu.option = option;
+            *text = Text2d::new(get_options_text(&settings, &actions.controls, option));
+        }
+        return;
+    }
+
+    if actions.just_pressed(Action::Pause) || actions.just_pressed(Action::Back) {
+        pause_state.set(PauseState::Running);
+        return;
+    }
+
+    // Move highlighted entry
+    let mut selected = menu.selected;
+    if actions.just_pressed(Action::Up) {
+        selected = (selected + PAUSE_ENTRIES.len() - 1) % PAUSE_ENTRIES.len();
+    }
+    if actions.just_pressed(Action::Down) {
+        selected = (selected + 1) % PAUSE_ENTRIES.len();
+    }
+    if selected != menu.selected {
+        audio.play(AudioBus::Ui, &game.sound_menu);
+        menu.selected = selected;
+        *text = Text2d::new(get_pause_text(&game, selected));
+    }
+    if !actions.just_pressed(Action::Confirm) {
+        return;
+    }
+
+    match menu.selected {
+        0 => pause_state.set(PauseState::Running),
+        1 => {
+            // Enter the level again with the lives, score and time it started with
+            game.nlives = recorder.config.nlives;
+            game.score = recorder.config.score;
+            game.play_time = game.level_play_time;
+            if let Some(mut player) = player {
+                *player = ReplayPlayer::new(player.replay.clone());
+            }
+            pause_state.set(PauseState::Running);
+            game_state.set(GameState::Game);
+        }
+        2 => {
+            menu.settings = true;
+            menu.option = 0;
+            *text = Text2d::new(get_options_text(&settings, &actions.controls, 0));
+        }
+        _ if game.playtest => game_state.set(GameState::Editor),
+        _ => {
+            commands.remove_resource::<ReplayPlayer>();
+            game_state.set(GameState::Menu);
+        }
+    }
+}
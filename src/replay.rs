@@ -0,0 +1,300 @@
+use crate::consts::*;
+use crate::save::*;
+use crate::sim::*;
+
+use rand::distr::weighted::WeightedIndex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+use bevy::prelude::*;
+
+#[derive(Serialize, Deserialize, Clone)]
+/// Everything needed to play a level again exactly as it was played
+pub struct Replay {
+    /// Level index in the campaign (only informative, the bricks are in `config`)
+    pub level: usize,
+    /// Simulation steps per second
+    pub tick_hz: f64,
+    /// Seed of the level random number generator
+    pub seed: u64,
+    /// Settings the level was started with
+    pub config: SimConfig,
+    /// Input of every step, as runs of (number of steps, input)
+    pub inputs: Vec<(u32, SimInput)>,
+}
+
+#[derive(Debug, Error)]
+/// Errors found while reading or writing a replay file
+pub enum ReplayError {
+    #[error("could not access replay file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse replay file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write replay file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("replay has {0} bricks instead of {GRID_ROWS}x{GRID_COLS}")]
+    WrongBrickCount(usize),
+    #[error("replay has an invalid brick variant {0}")]
+    InvalidBrick(usize),
+    #[error("replay has invalid barrel weights")]
+    InvalidWeights,
+    #[error("replay has an invalid tick rate")]
+    InvalidTickRate,
+    #[error("replay starts without lives")]
+    NoLives,
+}
+
+impl Replay {
+    pub fn new(level: usize, tick_hz: f64, seed: u64, config: SimConfig) -> Self {
+        Replay {
+            level,
+            tick_hz,
+            seed,
+            config,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Records the input of one more step
+    pub fn push(&mut self, input: SimInput) {
+        match self.inputs.last_mut() {
+            Some((steps, last)) if *last == input => *steps += 1,
+            _ => self.inputs.push((1, input)),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let replay: Replay = ron::de::from_bytes(&fs::read(path)?)?;
+        if replay.config.bricks.len() != GRID_ROWS * GRID_COLS {
+            return Err(ReplayError::WrongBrickCount(replay.config.bricks.len()));
+        }
+        if let Some(&variant) = replay
+            .config
+            .bricks
+            .iter()
+            .flatten()
+            .find(|v| **v >= BRICK_TYPES)
+        {
+            return Err(ReplayError::InvalidBrick(variant));
+        }
+        if WeightedIndex::new(&replay.config.barrel_weights).is_err() {
+            return Err(ReplayError::InvalidWeights);
+        }
+        if !replay.tick_hz.is_finite() || replay.tick_hz <= 0. {
+            return Err(ReplayError::InvalidTickRate);
+        }
+        if replay.config.nlives == 0 {
+            return Err(ReplayError::NoLives);
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        fs::write(path, ron::ser::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+/// Replay of the level being played, saved when the level ends
+pub struct ReplayRecorder(pub Replay);
+
+#[derive(Resource)]
+/// Replay given with -p, played instead of reading the keyboard
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    /// Current run of `replay.inputs`
+    run: usize,
+    /// Steps already played from the current run
+    step: u32,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            run: 0,
+            step: 0,
+        }
+    }
+
+    /// Input for the next step, or None when the replay is over
+    pub fn next_input(&mut self) -> Option<SimInput> {
+        let (steps, input) = *self.replay.inputs.get(self.run)?;
+        self.step += 1;
+        if self.step >= steps {
+            self.run += 1;
+            self.step = 0;
+        }
+        Some(input)
+    }
+}
+
+/// Saves the replay of the last level played, one per profile
+pub fn replay_save_last(replay: &Replay) {
+    let dir = data_dir().join(REPLAYS_DIRNAME);
+    let path = dir.join(format!("{}.replay.ron", profile()));
+    if let Err(err) = fs::create_dir_all(&dir)
+        .map_err(ReplayError::from)
+        .and_then(|_| replay.save(&path))
+    {
+        error!("{err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Replays of every level of the main campaign, cleared without losing a life
+    const FIXTURES_DIR: &str = "assets/replays";
+    /// Score at the end of each fixture
+    const FIXTURE_SCORES: [u32; 8] = [28220, 15850, 28970, 30780, 20690, 52440, 30840, 21800];
+
+    fn fixture_path(level: usize) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(FIXTURES_DIR)
+            .join(format!("level{level}.replay.ron"))
+    }
+
+    /// Settings of a level at the start of a new game
+    fn level_config(level: usize) -> SimConfig {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets/levels")
+            .join(format!("level{level}.level.ron"));
+        let file: LevelFile = ron::de::from_bytes(&fs::read(path).unwrap()).unwrap();
+        SimConfig {
+            bricks: grid_parse(&file.grid).unwrap(),
+            timer: file.timer,
+            nlives: NLIVES,
+            score: 0,
+            portal_unlocked: false,
+            meanies: false,
+            barrel_weights: BARREL_WEIGHTS.to_vec(),
+            powerup_duration: file.powerup_duration,
+        }
+    }
+
+    /// Seconds of a step, rounded as the fixed timestep of the game does
+    fn step_secs(replay: &Replay) -> f32 {
+        Duration::from_secs_f64(1. / replay.tick_hz).as_secs_f32()
+    }
+
+    /// Plays a replay to its end. Returns the simulation and its events
+    fn play(replay: &Replay) -> (Sim, Vec<SimEvent>) {
+        let mut sim = Sim::new(replay.config.clone());
+        let mut rng = StdRng::seed_from_u64(replay.seed);
+        let mut player = ReplayPlayer::new(replay.clone());
+        let mut events = Vec::new();
+        while let Some(input) = player.next_input() {
+            sim.step(step_secs(replay), &input, &mut rng);
+            events.append(&mut sim.events);
+        }
+        (sim, events)
+    }
+
+    /// Where a falling ball reaches the top of the paddle, if no brick is in the way
+    fn landing_x(ball: &SimBall) -> f32 {
+        let min_x = BALLAREA_MINX + BALL_RADIUS;
+        let width = BALLAREA_MAXX - BALL_RADIUS - min_x;
+        let top = PADDLE_Y + PADDLE_SIZES[0].y / 2. + BALL_RADIUS;
+        let fall = (ball.pos.y - top) / -ball.direction.y;
+        // Bounces on the walls are unfolded into a straight line
+        let x = (ball.pos.x + ball.direction.x * fall - min_x).rem_euclid(2. * width);
+        min_x + if x > width { 2. * width - x } else { x }
+    }
+
+    /// Input of a player waiting under the falling ball that lands first, hitting it
+    /// with different parts of the paddle over time, and leaving once the portal opens
+    fn autopilot(sim: &Sim, step: u32) -> SimInput {
+        let target_x = if sim.portal_open {
+            Some(BALLAREA_MAXX + PADDLE_SIZES[sim.paddle.variant].x)
+        } else {
+            let offset = ((step / 600) % 5) as f32 * 8. - 16.;
+            sim.balls
+                .iter()
+                .filter(|ball| !ball.caught && ball.direction.y < 0.)
+                .min_by(|a, b| a.pos.y.total_cmp(&b.pos.y))
+                .map(|ball| landing_x(ball) + offset)
+        };
+        let dx = target_x.map_or(0., |x| x - sim.paddle.x);
+        SimInput {
+            dir: if dx.abs() < 5. { 0. } else { dx.signum() },
+            boost: true,
+            fire: true,
+            ..SimInput::default()
+        }
+    }
+
+    /// Records the fixtures again, after a change to the rules:
+    /// cargo test record_perfect_runs -- --ignored
+    /// then update `FIXTURE_SCORES` with the scores printed
+    #[test]
+    #[ignore]
+    fn record_perfect_runs() {
+        fs::create_dir_all(fixture_path(0).parent().unwrap()).unwrap();
+        for level in 0..FIXTURE_SCORES.len() {
+            // Seeds are tried in order until the autopilot clears the level
+            let replay = (0..100)
+                .find_map(|seed| {
+                    let mut replay = Replay::new(level, SIM_TICK_HZ, seed, level_config(level));
+                    let mut sim = Sim::new(replay.config.clone());
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    for step in 0..(600. * SIM_TICK_HZ) as u32 {
+                        let input = autopilot(&sim, step);
+                        replay.push(input);
+                        sim.step(step_secs(&replay), &input, &mut rng);
+                        let lost = sim
+                            .events
+                            .iter()
+                            .any(|e| matches!(e, SimEvent::LifeLost(_)));
+                        if lost {
+                            return None;
+                        }
+                        if sim.status == SimStatus::LevelExit {
+                            println!("level {level}: seed {seed}, score {}", sim.score);
+                            return Some(replay);
+                        }
+                    }
+                    None
+                })
+                .expect("no perfect run found");
+            replay.save(&fixture_path(level)).unwrap();
+        }
+    }
+
+    #[test]
+    fn perfect_runs_play_back() {
+        for (level, score) in FIXTURE_SCORES.iter().enumerate() {
+            let replay = Replay::load(&fixture_path(level)).unwrap();
+            assert_eq!(replay.level, level);
+            assert_eq!(replay.config.bricks, level_config(level).bricks);
+            let (sim, events) = play(&replay);
+            assert_eq!(sim.status, SimStatus::LevelExit, "level {level}");
+            assert_eq!(events.last(), Some(&SimEvent::LevelExit), "level {level}");
+            let lost = events.iter().any(|e| matches!(e, SimEvent::LifeLost(_)));
+            assert!(!lost, "level {level}");
+            assert_eq!(sim.bricks_left, 0, "level {level}");
+            assert!(sim.nlives > replay.config.nlives, "level {level}");
+            assert_eq!(sim.score, *score, "level {level}");
+        }
+    }
+
+    #[test]
+    fn replay_without_lives_is_refused() {
+        let mut replay = Replay::load(&fixture_path(0)).unwrap();
+        replay.config.nlives = 0;
+        let path = std::env::temp_dir().join("merino_breakout_test.replay.ron");
+        replay.save(&path).unwrap();
+        let result = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ReplayError::NoLives)));
+    }
+}
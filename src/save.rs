@@ -1,54 +1,307 @@
+use crate::codes::*;
 use crate::consts::*;
 use crate::game::*;
+use crate::highscores::*;
+use crate::settings::*;
 use crate::shop::*;
 
+use bevy::prelude::*;
 use dirs::home_dir;
-use std::fs::*;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use users::{get_current_uid, get_user_by_uid};
 
-pub fn game_save(game: &Game) {
-    let home = home_dir().unwrap_or_default();
-    let filepath = home.join(SAVE_FILENAME);
+/// Profile whose save file is used, chosen with --profile or in the menu
+static PROFILE: Mutex<String> = Mutex::new(String::new());
 
-    let Ok(file) = File::create(filepath) else {
-        return;
-    };
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+/// Totals over every game played
+pub struct Statistics {
+    /// Games that ended, by losing every life or reaching the end
+    pub games_played: u32,
+    /// Levels finished, counting every game
+    pub levels_completed: u32,
+    /// Seconds played in finished games
+    pub play_seconds: f64,
+    pub best_score: u32,
+}
 
-    let mut writer = BufWriter::new(file);
-    for s in game.secrets_generated.iter() {
-        if !s.is_empty() {
-            writeln!(writer, "{}", s).unwrap();
-        }
+impl Statistics {
+    /// Counts a game that ended with `score` after `seconds` of play
+    pub fn game_ended(&mut self, score: u32, seconds: f32) {
+        self.games_played += 1;
+        self.play_seconds += seconds as f64;
+        self.best_score = self.best_score.max(score);
+    }
+
+    pub fn save(&self) {
+        save_update(|save| save.statistics = self.clone());
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+/// Everything kept between runs, stored in a single file
+pub struct SaveData {
+    /// Format of the file, to convert saves written by older versions
+    pub version: u32,
+    /// Letters identifying the player in the codes, never changed once generated
+    pub player_id: String,
+    /// Name chosen by the player on the first run
+    pub handle: String,
+    /// Codes of the secrets discovered by the player
+    pub secrets_generated: Vec<String>,
+    /// Codes of the secrets entered in the shop
+    pub secrets_unlocked: Vec<String>,
+    /// Codes of the levels whose portal stays open
+    pub levels_unlocked: Vec<String>,
+    pub settings: Settings,
+    pub statistics: Statistics,
+    pub highscores: HighScores,
+}
+
+#[derive(Debug, Error)]
+/// Errors found while reading or writing the save file
+pub enum SaveError {
+    #[error("could not access save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse save file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write save file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("save file version {0} is newer than this game")]
+    UnsupportedVersion(u32),
+}
+
+impl SaveData {
+    fn path() -> PathBuf {
+        data_dir()
+            .join(PROFILES_DIRNAME)
+            .join(format!("{}.ron", profile()))
     }
-    for s in game.secrets_unlocked.iter() {
-        if !s.is_empty() {
-            writeln!(writer, "{}", s).unwrap();
+
+    /// Reads the save file. Missing or broken files give the defaults
+    pub fn load() -> Self {
+        SaveData::read().unwrap_or_else(|err| {
+            error!("{err}");
+            SaveData::default()
+        })
+    }
+
+    fn read() -> Result<Self, SaveError> {
+        let bytes = match fs::read(SaveData::path()) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(SaveData::new_profile()),
+            Err(err) => return Err(err.into()),
+        };
+        let save: SaveData = ron::de::from_bytes(&bytes)?;
+        // Conversions from older formats go here
+        if save.version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(save.version));
         }
+        Ok(save)
     }
-    for s in game.levels_unlocked.iter() {
-        if !s.is_empty() {
-            writeln!(writer, "{}", s).unwrap();
+
+    /// Save of a new profile. Older versions made the codes of the legacy text save
+    /// with the name of the user, which the default profile keeps as its ID
+    fn new_profile() -> Self {
+        let mut save = SaveData {
+            version: SAVE_VERSION,
+            ..default()
+        };
+        let home = home_dir().unwrap_or_default();
+        if profile() == DEFAULT_PROFILE && home.join(LEGACY_SAVE_FILENAME).exists() {
+            save.player_id = legacy_player_id().unwrap_or_default();
         }
+        save
+    }
+
+    /// Writes to a temporary file first, so that a crash never leaves a half-written save
+    fn write(&self) -> Result<(), SaveError> {
+        let path = SaveData::path();
+        let tmp_path = path.with_extension("tmp");
+        let text = ron::ser::to_string_pretty(self, default())?;
+        fs::create_dir_all(data_dir().join(PROFILES_DIRNAME))?;
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+fn player_id_is_valid(id: &str) -> bool {
+    id.len() == PLAYER_ID_LEN && id.chars().all(|c| c.is_ascii_uppercase())
+}
+
+fn player_id_new() -> String {
+    let mut rng = rand::rng();
+    (0..PLAYER_ID_LEN)
+        .map(|_| rng.random_range('A'..='Z'))
+        .collect()
+}
+
+/// Name of the user padded with 'X', as older versions used it in the codes.
+/// None when the user has no name (e.g. in some containers)
+fn legacy_player_id() -> Option<String> {
+    let user = get_user_by_uid(get_current_uid())?;
+    let mut name = user.name().to_string_lossy().to_uppercase();
+    name.truncate(PLAYER_ID_LEN);
+    let id = format!("{name:X<PLAYER_ID_LEN$}");
+    player_id_is_valid(&id).then_some(id)
+}
+
+/// Folder of the files kept between runs (e.g. ~/.local/share/merino_breakout)
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir().unwrap_or_default().join(CONFIG_DIRNAME)
+}
+
+/// Name of the current profile, the one used last time if none was chosen yet
+pub fn profile() -> String {
+    let mut profile = PROFILE.lock().unwrap();
+    if profile.is_empty() {
+        *profile = fs::read_to_string(data_dir().join(PROFILE_FILENAME))
+            .ok()
+            .and_then(|name| profile_name(&name))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    }
+    profile.clone()
+}
+
+/// Makes a profile the current one, also for the next runs
+pub fn profile_select(name: &str) {
+    *PROFILE.lock().unwrap() = name.to_string();
+    let dir = data_dir();
+    if let Err(err) =
+        fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(PROFILE_FILENAME), name))
+    {
+        error!("Could not write {PROFILE_FILENAME}: {err}");
+    }
+}
+
+/// Checks a profile name, which is also the name of its file. Returns it in upper case
+pub fn profile_name(name: &str) -> Option<String> {
+    let name = name.trim().to_ascii_uppercase();
+    let valid = !name.is_empty()
+        && name.len() <= PROFILE_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then_some(name)
+}
+
+/// Profiles with a save file and the current one, in alphabetical order
+pub fn profiles() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(data_dir().join(PROFILES_DIRNAME))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "ron" {
+                return None;
+            }
+            profile_name(path.file_stem()?.to_str()?)
+        })
+        .collect();
+    let current = profile();
+    if !names.contains(&current) {
+        names.push(current);
     }
-    writer.flush().unwrap();
+    names.sort();
+    names
 }
 
-pub fn game_load(game: &mut Game) {
-    let home = home_dir().unwrap_or_default();
-    let filepath = home.join(SAVE_FILENAME);
-    let Ok(file) = File::open(filepath) else {
-        return;
+/// Changes part of the save file, keeping the rest as it is on disk.
+/// Files that cannot be read are left untouched instead of being overwritten
+pub fn save_update(update: impl FnOnce(&mut SaveData)) {
+    let mut save = match SaveData::read() {
+        Ok(save) => save,
+        Err(err) => {
+            error!("Not saving: {err}");
+            return;
+        }
     };
-    let reader = BufReader::new(file);
-    for line in reader.lines() {
-        let Ok(line) = line else { continue };
-        let _ = shop_code_add(game, line.as_str());
+    save.version = SAVE_VERSION;
+    update(&mut save);
+    if let Err(err) = save.write() {
+        error!("{err}");
+    }
+}
+
+fn codes(list: &[String]) -> Vec<String> {
+    list.iter().filter(|s| !s.is_empty()).cloned().collect()
+}
+
+pub fn game_save(game: &Game) {
+    save_update(|save| {
+        save.secrets_generated = codes(&game.secrets_generated);
+        save.secrets_unlocked = codes(&game.secrets_unlocked);
+        save.levels_unlocked = codes(&game.levels_unlocked);
+    });
+}
+
+/// Reads the save of the current profile into the game, and returns it for the rest
+pub fn game_load(game: &mut Game) -> SaveData {
+    let new_profile = !SaveData::path().exists();
+    let mut save = SaveData::load();
+    let new_id = !player_id_is_valid(&save.player_id);
+    if new_id {
+        save.player_id = player_id_new();
+    }
+    game.player_id = save.player_id.clone();
+    game.handle = save.handle.clone();
+    if new_profile || new_id {
+        // The identity is kept before anything else is saved
+        let id = game.player_id.clone();
+        save_update(|save| save.player_id = id);
+    }
+
+    // Codes are checked again, discovered secrets before the ones entered
+    let mut codes: Vec<String> = save
+        .secrets_generated
+        .iter()
+        .chain(&save.secrets_unlocked)
+        .chain(&save.levels_unlocked)
+        .cloned()
+        .collect();
+    if new_profile && profile() == DEFAULT_PROFILE {
+        // First run after the text save: one code per line
+        let home = home_dir().unwrap_or_default();
+        if let Ok(text) = fs::read_to_string(home.join(LEGACY_SAVE_FILENAME)) {
+            let lines = text.lines().filter(|line| !line.trim().is_empty());
+            codes.extend(lines.map(String::from));
+        }
+    }
+    let mut converted = false;
+    for code in codes {
+        // Codes of older versions are kept in the current format
+        let code = match Code::parse_legacy(&code) {
+            Ok(legacy) => {
+                converted = true;
+                legacy.encode()
+            }
+            Err(_) => code,
+        };
+        if let Err(err) = shop_code_add(game, &code) {
+            warn!("Ignoring code {code} of the save: {err}");
+        }
+    }
+    if converted {
+        game_save(game);
     }
-    shop_process_secrets(game);    
+    shop_process_secrets(game);
+    save
 }
 
 pub fn game_reset() {
-    let home = home_dir().unwrap_or_default();
-    let filepath = home.join(SAVE_FILENAME);
-    let _ = remove_file(filepath);
+    save_update(|save| {
+        save.secrets_generated.clear();
+        save.secrets_unlocked.clear();
+        save.levels_unlocked.clear();
+    });
 }
@@ -0,0 +1,190 @@
+use crate::audio::*;
+use crate::consts::*;
+use crate::controls::*;
+use crate::save::*;
+
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, PrimaryWindow, WindowMode, WindowResized};
+use serde::{Deserialize, Serialize};
+
+/// Entries of the options screen, in order
+const OPTIONS_ENTRIES: [&str; 7] = [
+    "MASTER VOLUME",
+    "MUSIC VOLUME",
+    "SFX VOLUME",
+    "UI VOLUME",
+    "FULLSCREEN",
+    "INTEGER SCALING",
+    "VSYNC",
+];
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+#[serde(default)]
+/// Player options, stored in the user's config folder
+pub struct Settings {
+    /// Volume of every sound (0 to 1)
+    pub master_volume: f32,
+    /// Volume of the menu and level music (0 to 1)
+    pub music_volume: f32,
+    /// Volume of the sound effects (0 to 1)
+    pub sfx_volume: f32,
+    /// Volume of the menu sounds (0 to 1)
+    pub ui_volume: f32,
+    pub fullscreen: bool,
+    /// Scale the game by whole numbers only, for sharp pixels
+    pub integer_scaling: bool,
+    pub vsync: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 1.,
+            music_volume: 1.,
+            sfx_volume: 1.,
+            ui_volume: 1.,
+            fullscreen: false,
+            integer_scaling: false,
+            vsync: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn save(&self) {
+        save_update(|save| save.settings = self.clone());
+    }
+
+    /// Volume of a sound on a bus, given the volume of the sound itself
+    pub fn volume(&self, bus: AudioBus, volume: f32) -> Volume {
+        let bus_volume = match bus {
+            AudioBus::Music => self.music_volume,
+            AudioBus::Sfx => self.sfx_volume,
+            AudioBus::Ui => self.ui_volume,
+        };
+        Volume::Linear(volume * self.master_volume * bus_volume)
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        match self.fullscreen {
+            true => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
+            false => WindowMode::Windowed,
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        match self.vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+// The settings are read with the rest of the save by the splash screen. The window
+// opens with the default mode and takes the one of the settings on the next update
+pub fn settings_plugin(app: &mut App) {
+    app.init_resource::<Settings>()
+        .add_systems(Update, settings_apply);
+}
+
+/// Applies changed settings to the window and camera
+fn settings_apply(
+    settings: Res<Settings>,
+    mut resized: MessageReader<WindowResized>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut projection: Single<&mut Projection, With<Camera2d>>,
+) {
+    let resized = resized.read().count() > 0;
+    if !settings.is_changed() && !resized {
+        return;
+    }
+
+    if settings.is_changed() {
+        window.mode = settings.window_mode();
+        window.present_mode = settings.present_mode();
+    }
+
+    // Largest whole scale that fits, or any scale that fits
+    if let Projection::Orthographic(ortho) = projection.as_mut() {
+        if settings.integer_scaling {
+            let fit = (window.width() / SCREEN_WIDTH).min(window.height() / SCREEN_HEIGHT);
+            ortho.scaling_mode = bevy::camera::ScalingMode::WindowSize;
+            ortho.scale = 1. / fit.floor().max(1.);
+        } else {
+            ortho.scaling_mode = bevy::camera::ScalingMode::AutoMin {
+                min_width: SCREEN_WIDTH,
+                min_height: SCREEN_HEIGHT,
+            };
+            ortho.scale = 1.;
+        }
+    }
+}
+
+/// Text of the options screen
+pub fn get_options_text(settings: &Settings, controls: &Controls, selected: usize) -> String {
+    let on_off = |on: bool| if on { "ON" } else { "OFF" }.to_string();
+    let percent = |volume: f32| format!("{:.0}%", volume * 100.);
+    let values = [
+        percent(settings.master_volume),
+        percent(settings.music_volume),
+        percent(settings.sfx_volume),
+        percent(settings.ui_volume),
+        on_off(settings.fullscreen),
+        on_off(settings.integer_scaling),
+        on_off(settings.vsync),
+    ];
+    let mut text = String::from("OPTIONS\n\n");
+    for (idx, (entry, value)) in OPTIONS_ENTRIES.iter().zip(values).enumerate() {
+        let marker = if idx == selected { "> " } else { "  " };
+        text.push_str(&format!("{marker}{entry:<16} {value:>4}\n\n"));
+    }
+    text.push_str(&format!(
+        "{}/{} to change, {} to go BACK",
+        controls.key_name(Action::MoveLeft),
+        controls.key_name(Action::MoveRight),
+        controls.key_name(Action::Back)
+    ));
+    text
+}
+
+/// Handles the input of the options screen. Returns true when the player leaves it
+pub fn options_update(
+    just_pressed: impl Fn(Action) -> bool,
+    settings: &mut ResMut<Settings>,
+    selected: &mut usize,
+) -> bool {
+    if just_pressed(Action::Back) {
+        settings.save();
+        return true;
+    }
+    if just_pressed(Action::Up) {
+        *selected = (*selected + OPTIONS_ENTRIES.len() - 1) % OPTIONS_ENTRIES.len();
+    }
+    if just_pressed(Action::Down) {
+        *selected = (*selected + 1) % OPTIONS_ENTRIES.len();
+    }
+
+    // Left/Right change the volumes by steps, and also toggle the rest like Confirm
+    let mut step = 0.;
+    if just_pressed(Action::MoveLeft) {
+        step -= OPTIONS_VOLUME_STEP;
+    }
+    if just_pressed(Action::MoveRight) {
+        step += OPTIONS_VOLUME_STEP;
+    }
+    let toggle = step != 0. || just_pressed(Action::Confirm);
+    let volume =
+        |v: f32| (((v + step) / OPTIONS_VOLUME_STEP).round() * OPTIONS_VOLUME_STEP).clamp(0., 1.);
+    match *selected {
+        0 if step != 0. => settings.master_volume = volume(settings.master_volume),
+        1 if step != 0. => settings.music_volume = volume(settings.music_volume),
+        2 if step != 0. => settings.sfx_volume = volume(settings.sfx_volume),
+        3 if step != 0. => settings.ui_volume = volume(settings.ui_volume),
+        4 if toggle => settings.fullscreen = !settings.fullscreen,
+        5 if toggle => settings.integer_scaling = !settings.integer_scaling,
+        6 if toggle => settings.vsync = !settings.vsync,
+        _ => {}
+    }
+    false
+}
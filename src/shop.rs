@@ -1,4 +1,6 @@
+use crate::codes::*;
 use crate::consts::*;
+use crate::controls::*;
 use crate::game::*;
 use crate::save::*;
 
@@ -6,8 +8,6 @@ use super::{GameState, despawn_all};
 use bevy::input::ButtonState;
 use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
-use ciphers::{Cipher, Vigenere};
-use rand::distr::weighted::WeightedIndex;
 
 #[derive(Component)]
 struct ShopTag;
@@ -21,6 +21,8 @@ struct ShopSecretsText;
 #[derive(Resource)]
 pub struct Shop {
     input: String,
+    /// Letter picked with the gamepad d-pad, shown after the input
+    pad_letter: Option<char>,
 }
 
 pub fn secret_is_discovered(secret: usize, game: &Game) -> bool {
@@ -48,6 +50,7 @@ pub fn shop_plugin(app: &mut App) {
 fn shop_startup(mut commands: Commands) {
     let shop = Shop {
         input: String::new(),
+        pad_letter: None,
     };
     commands.insert_resource(shop);
     // for l in ["A", "B", "C", "D", "E", "F", "G", "H"] {
@@ -105,36 +108,67 @@ fn shop_keyboard_input(
     mut shop: ResMut<Shop>,
     mut event: MessageReader<KeyboardInput>,
     text: Single<&mut Text2d, With<ShopCodeText>>,
+    actions: Actions,
 ) {
     let mut modified: bool = false;
+
+    if actions.just_pressed(Action::Back) {
+        shop.input.clear();
+        shop.pad_letter = None;
+        game_state.set(GameState::Menu);
+        return;
+    }
+    if actions.just_pressed(Action::Delete) {
+        shop.input.pop();
+        modified = true;
+    }
+
+    // Without a keyboard, Up/Down pick a digit or letter and Confirm adds it
+    let pad_chars = SHOP_PAD_CHARS.as_bytes();
+    let pad_idx = shop
+        .pad_letter
+        .and_then(|c| pad_chars.iter().position(|p| *p as char == c))
+        .unwrap_or(0);
+    if actions.just_pressed(Action::Up) {
+        let idx = (pad_idx + pad_chars.len() - 1) % pad_chars.len();
+        shop.pad_letter = Some(pad_chars[idx] as char);
+        modified = true;
+    }
+    if actions.just_pressed(Action::Down) {
+        shop.pad_letter = Some(pad_chars[(pad_idx + 1) % pad_chars.len()] as char);
+        modified = true;
+    }
+    if actions.just_pressed(Action::Confirm) {
+        match shop.pad_letter {
+            Some(c) if shop.input.len() < CODE_LEN => shop.input.push(c),
+            Some(_) => {}
+            None => shop.pad_letter = Some(pad_chars[0] as char),
+        }
+        modified = true;
+    }
+
     for ev in event.read() {
         if ev.state == ButtonState::Released {
             continue;
         }
-        match &ev.logical_key {
-            Key::Escape => {
-                shop.input.clear();
-                game_state.set(GameState::Menu);
-                break;
-            }
-            Key::Backspace => {
-                shop.input.pop();
-                modified = true;
-            }
-            Key::Character(input) => {
-                for c in input.chars() {
-                    if c.is_ascii_alphabetic() && shop.input.len() < CODE_LEN {
-                        shop.input.push(c);
-                        modified = true;
-                    }
+        if let Key::Character(input) = &ev.logical_key {
+            for c in input.chars() {
+                if c.is_ascii_alphanumeric() && shop.input.len() < CODE_LEN {
+                    shop.input.push(c);
+                    modified = true;
                 }
             }
-            _ => {}
         }
     }
     if modified {
         shop.input = shop.input.to_uppercase();
-        text.into_inner().0 = format!("{:_<CODE_LEN$}", shop.input);
+        let mut shown = shop.input.clone();
+        if let Some(c) = shop.pad_letter
+            && shown.len() < CODE_LEN
+        {
+            shown.push(c);
+        }
+        text.into_inner().0 = format!("{shown:_<CODE_LEN$}");
     }
 }
 
@@ -143,6 +177,7 @@ fn shop_update(
     mut game: ResMut<Game>,
     mut code_text: Single<&mut Text2d, With<ShopCodeText>>,
     mut secrets_text: Single<&mut Text2d, (With<ShopSecretsText>, Without<ShopCodeText>)>,
+    controls: Res<Controls>,
 ) {
     // Add code keyed in by user
     if shop.input.len() == CODE_LEN {
@@ -155,7 +190,7 @@ fn shop_update(
                 shop_process_secrets(&mut game);
             }
             Err(e) => {
-                code_text.push_str(e.as_str());
+                code_text.push_str(&e.to_string());
             }
         }
         shop.input.clear();
@@ -196,40 +231,36 @@ fn shop_update(
         secrets_text.push_str("\nSHARE THESE WITH OTHER PLAYERS:\n\n");
         generated_txt = generated_txt.trim_end().to_string();
         secrets_text.push_str(&generated_txt);
-        secrets_text.push_str("\n(Press ESC to return to menu)");
+        secrets_text.push_str(&format!(
+            "\n(Press {} to return to menu)",
+            controls.key_name(Action::Back)
+        ));
     }
 }
 
-pub fn shop_code_add(game: &mut Game, code: &str) -> Result<usize, String> {
-    let secret = decode(code).unwrap_or_default();
-    if code.len() == CODE_LEN
-        && secret.len() == CODE_LEN - 1
-        && secret.as_bytes()[USERNAME_LEN + 1] - b'A' < NLEVELS as u8
-    {
-        let idx: usize = (secret.as_bytes()[USERNAME_LEN + 1] - b'A') as usize;
-        if secret[..game.username.len()] == game.username {
-            match secret.as_bytes()[USERNAME_LEN] {
-                b'X' => {
-                    game.secrets_generated[idx] = code.to_string();
-                    return Ok(idx);
-                }
-                b'L' => {
-                    game.levels_unlocked[idx] = code.to_string();
-                    return Ok(idx);
-                }
-                _ => (),
-            }
-        } else if secret.as_bytes()[USERNAME_LEN] == b'X' {
-            if !secret_is_discovered(idx, game) {
-                return Err("Secret not discovered yet".to_string());
-            } else if secret_is_unlocked(idx, game) {
-                return Err("Duplicated".to_string());
-            }
-            game.secrets_unlocked[idx] = code.to_string();
-            return Ok(idx);
+/// Adds a code typed in the shop or read from the save. Returns the index of its secret or level
+pub fn shop_code_add(game: &mut Game, code: &str) -> Result<usize, CodeError> {
+    let parsed = Code::parse(code)?;
+    let slot = parsed.slot(
+        &game.player_id,
+        &game.secrets_generated,
+        &game.secrets_unlocked,
+    )?;
+    let code = code.trim().to_ascii_uppercase();
+    match slot {
+        CodeSlot::Discovered(idx) => {
+            game.secrets_generated[idx] = code;
+            Ok(idx)
+        }
+        CodeSlot::Unlocked(idx) => {
+            game.secrets_unlocked[idx] = code;
+            Ok(idx)
+        }
+        CodeSlot::Level(idx) => {
+            game.levels_unlocked[idx] = code;
+            Ok(idx)
         }
     }
-    Err("Incorrect".to_string())
 }
 
 pub fn shop_process_secrets(game: &mut Game) {
@@ -243,71 +274,28 @@ pub fn shop_process_secrets(game: &mut Game) {
     if secret_is_unlocked(Secret::Multiball as usize, game) {
         barrel_weights[4] = 4;
     }
+    if secret_is_unlocked(Secret::Hell as usize, game) {
+        barrel_weights[7] = 1; // Portal Barrel
+    }
     if secret_is_unlocked(Secret::Gun as usize, game)
         && secret_is_unlocked(Secret::Magnet as usize, game)
         && secret_is_unlocked(Secret::Multiball as usize, game)
     {
         barrel_weights[9] = 2; // Extra Time Barrel
     }
-    game.barrel_dist = WeightedIndex::new(barrel_weights).unwrap();
+    game.barrel_weights = barrel_weights;
 
     if secret_is_unlocked(Secret::XRay as usize, game) {
         game.h_bricks[14] = game.h_bricks[10].clone();
     }
 }
 
-pub fn shop_code_generate_new(game: &mut Game, t: char, idx: u8) {
-    let secret_idx_letter = (idx + b'A') as char;
-    let decoded: String = format!("{}{}{}", game.username, t, secret_idx_letter);
-    let encoded: String = encode(&decoded);
-    let _ = shop_code_add(game, &encoded);
+pub fn shop_code_generate_new(game: &mut Game, kind: CodeKind, idx: usize) {
+    let code = Code {
+        player_id: game.player_id.clone(),
+        kind,
+        idx,
+    };
+    let _ = shop_code_add(game, &code.encode());
     game_save(game);
 }
-
-fn calculate_crc(input: &str) -> u8 {
-    let mut crc: u8 = 0;
-    for c in input.chars() {
-        crc = crc.wrapping_add(c as u8);
-        crc <<= 1;
-    }
-    // Convert CRC to uppercase letter
-    b'A' + (crc % 26)
-}
-
-fn encode(s: &str) -> String {
-    let crc: u8 = calculate_crc(s);
-    // Compute Key for encryption
-    let key_rotation: usize = (crc as usize) % CIPHER_KEY.len();
-    let mut key = String::with_capacity(CIPHER_KEY.len());
-    key.push_str(&CIPHER_KEY[key_rotation..]); // First part: from rotation to end
-    key.push_str(&CIPHER_KEY[..key_rotation]); // Second part: from start to rotation
-    // Encode
-    let vigenere = Vigenere::new(&key);
-    let mut encoded: String = vigenere.encipher(s).unwrap();
-    // Add crc to the end of the encoded string
-    encoded.push(crc as char);
-    encoded
-}
-
-pub fn decode(s: &str) -> Result<String, String> {
-    if s.is_empty() {
-        return Err("Attempting to decode empty string".to_string());
-    }
-    // Extract crc from the end
-    let expected_crc: u8 = s.as_bytes()[s.len() - 1];
-    let s: &str = &s[..s.len() - 1];
-    // Compute key for decryption
-    let key_rotation: usize = (expected_crc as usize) % CIPHER_KEY.len();
-    let mut key = String::with_capacity(CIPHER_KEY.len());
-    key.push_str(&CIPHER_KEY[key_rotation..]); // First part: from rotation to end
-    key.push_str(&CIPHER_KEY[..key_rotation]); // Second part: from start to rotation
-    // Decode
-    let vigenere = Vigenere::new(&key);
-    let decoded: String = vigenere.decipher(s).unwrap();
-    // Check crc
-    if calculate_crc(&decoded) == expected_crc {
-        Ok(decoded)
-    } else {
-        Err("CRC Failed".to_string())
-    }
-}
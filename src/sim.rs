@@ -0,0 +1,1270 @@
+use crate::collisions::*;
+use crate::consts::*;
+use bevy::math::Vec2;
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::*;
+use std::mem;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+/// Player input for one simulation step
+pub struct SimInput {
+    /// Paddle direction, from -1 (left) to 1 (right)
+    pub dir: f32,
+    /// Whether the paddle moves at full speed
+    pub boost: bool,
+    /// Extra paddle speed, from 0 (slowest) to 1 (full speed), for analog sticks
+    #[serde(default)]
+    pub throttle: f32,
+    /// Position the paddle moves to at full speed, for the mouse (overrides `dir`)
+    #[serde(default)]
+    pub target_x: Option<f32>,
+    /// Whether caught balls are released and the gun is fired
+    pub fire: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Things that happened during a simulation step, to be shown by the presentation layer
+pub enum SimEvent {
+    /// A ball bounced on a wall at the given position
+    WallHit { pos: Vec2, speed: f32 },
+    /// A ball bounced on the paddle
+    PaddleHit { pos: Vec2, speed: f32 },
+    /// A ball was caught by the magnet paddle
+    BallCaught(Vec2),
+    /// A ball bounced on one or more bricks
+    BallHitBricks { pos: Vec2, speed: f32 },
+    /// A brick was hit (variant before the hit)
+    BrickHit { idx: usize, variant: usize },
+    /// A brick was destroyed
+    BrickDestroyed { idx: usize },
+    /// The gun paddle fired a pair of bullets
+    BulletsFired,
+    /// A bullet exploded against a brick or the top wall
+    BulletHit(Vec2),
+    /// The paddle caught a barrel
+    BarrelCaught { variant: usize },
+    /// Ball speed changed after many impacts or a barrel
+    SpeedChanged { speed: f32 },
+    /// A ball hit several bricks without touching the paddle
+    Combo { multiplier: u32 },
+    /// Ball direction was nudged after many impacts without touching the paddle
+    BallNudged { speed: f32 },
+    /// A meanie was destroyed by the paddle, a ball or a bullet (`shot`)
+    MeanieDestroyed { pos: Vec2, shot: bool },
+    /// The last ball was lost (with the paddle at the given position) and a new one was served
+    LifeLost(Vec2),
+    /// All bricks were destroyed
+    ExtraLife,
+    /// The exit portal started opening
+    PortalOpening,
+    /// The exit portal started closing
+    PortalClosing,
+    /// The level countdown reached zero
+    CountdownFinished,
+    /// The paddle went through the open portal
+    LevelExit,
+    /// The last ball of the last life was lost
+    GameOver,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// Whether the level is still being played
+pub enum SimStatus {
+    Playing,
+    LevelExit,
+    GameOver,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// States for the level exit portal
+pub enum PortalState {
+    /// Portal is closed and inactive
+    Closed,
+    /// Portal is in the process of opening
+    Opening,
+    /// Portal is fully open and can be used
+    Open,
+    /// Portal is in the process of closing
+    Closing,
+}
+
+#[derive(Clone, Debug)]
+/// Player-controlled paddle
+pub struct SimPaddle {
+    /// Horizontal position of the paddle center
+    pub x: f32,
+    /// Horizontal position at the start of the last step
+    pub prev_x: f32,
+    /// Current paddle variant/type (affects abilities)
+    pub variant: usize,
+    /// Seconds until the gun can fire again
+    pub gun_cooldown: f32,
+}
+
+#[derive(Clone, Debug)]
+/// Ball with its physics properties
+pub struct SimBall {
+    /// Identifier used by the presentation layer
+    pub id: u32,
+    /// Position of the ball center
+    pub pos: Vec2,
+    /// Position at the start of the last step
+    pub prev_pos: Vec2,
+    /// Total number of impacts (used for speed increases)
+    pub impacts: u32,
+    /// Impacts since last paddle contact (used for direction changes)
+    pub impacts_since_paddle: u32,
+    /// Bricks hit since last paddle contact (multiplies brick points)
+    pub combo: u32,
+    /// Current speed of the ball in pixels per second
+    pub speed: f32,
+    /// Speed before the active speed barrel, restored when its effect wears off
+    pub pre_effect_speed: Option<f32>,
+    /// Normalized direction vector
+    pub direction: Vec2,
+    /// Distance (less than a pixel) left over from previous steps
+    pub travel: f32,
+    /// Whether the ball is caught by a magnetic paddle
+    pub caught: bool,
+    /// Whether the ball is currently in collision with the paddle
+    /// Used to prevent multiple collisions in a single frame
+    pub in_collision: bool,
+}
+
+#[derive(Clone, Debug)]
+/// Bullet shot by the gun paddle
+pub struct SimBullet {
+    /// Identifier used by the presentation layer
+    pub id: u32,
+    /// Position of the bullet center
+    pub pos: Vec2,
+    /// Position at the start of the last step
+    pub prev_pos: Vec2,
+}
+
+#[derive(Clone, Debug)]
+/// Power-up barrel falling towards the paddle
+pub struct SimBarrel {
+    /// Identifier used by the presentation layer
+    pub id: u32,
+    /// Position of the barrel center
+    pub pos: Vec2,
+    /// Position at the start of the last step
+    pub prev_pos: Vec2,
+    /// Barrel type (see `BARREL_TITLES`)
+    pub variant: usize,
+}
+
+#[derive(Clone, Debug)]
+/// Enemy wandering down the screen
+pub struct SimMeanie {
+    /// Identifier used by the presentation layer
+    pub id: u32,
+    /// Position of the meanie center
+    pub pos: Vec2,
+    /// Position at the start of the last step
+    pub prev_pos: Vec2,
+    /// Meanie type (affects appearance and size)
+    pub variant: usize,
+    /// Speed in pixels per second
+    pub speed: f32,
+    /// Direction of the random walk
+    pub angle: f32,
+    /// Seconds since the meanie appeared, used to pick the animation frame
+    pub age: f32,
+}
+
+impl SimMeanie {
+    /// Animation frame currently shown (the collision box depends on it)
+    pub fn frame(&self) -> usize {
+        (self.age * MEANIES_FRAMERATE) as usize % MEANIES_NFRAMES[self.variant]
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Barrel effect that wears off after a while
+pub struct SimEffect {
+    /// Barrel type that caused the effect (see `BARREL_TITLES`)
+    pub variant: usize,
+    /// Seconds until the effect wears off
+    pub time_left: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+/// Settings for a new level, derived from the level and the player progress
+pub struct SimConfig {
+    /// Brick variant for every grid slot (None for empty slots)
+    pub bricks: Vec<Option<usize>>,
+    /// Seconds on the level countdown
+    pub timer: f32,
+    /// Lives at the start of the level
+    pub nlives: u32,
+    /// Score at the start of the level
+    #[serde(default)]
+    pub score: u32,
+    /// Whether the portal is open from the start and closes with the countdown
+    pub portal_unlocked: bool,
+    /// Whether meanies come out of the top portals
+    pub meanies: bool,
+    /// Weights of the barrel types
+    pub barrel_weights: Vec<u32>,
+    /// Seconds that paddle and speed barrels last (None: until the next barrel or life)
+    #[serde(default)]
+    pub powerup_duration: Option<f32>,
+}
+
+#[derive(Clone)]
+/// Rules of the game for one level: ball movement, brick hits, barrel effects,
+/// lives and portal state. Advanced with `step`, it knows nothing about
+/// rendering or audio and reports what happened through `events`.
+pub struct Sim {
+    pub status: SimStatus,
+    pub paddle: SimPaddle,
+    pub balls: Vec<SimBall>,
+    pub bullets: Vec<SimBullet>,
+    pub barrels: Vec<SimBarrel>,
+    pub meanies: Vec<SimMeanie>,
+    /// Barrel effects that will wear off, if the level has a power-up duration
+    pub effects: Vec<SimEffect>,
+    /// Brick variant for every grid slot (None for empty slots)
+    pub bricks: Vec<Option<usize>>,
+    /// Number of bricks remaining to be destroyed
+    pub bricks_left: u32,
+    /// Number of lives remaining
+    pub nlives: u32,
+    /// Points scored in the game so far
+    pub score: u32,
+    /// Seconds remaining on the level countdown
+    pub time_left: f32,
+    pub portal_state: PortalState,
+    /// Whether the exit portal is fully open
+    pub portal_open: bool,
+    /// Seconds until the portal finishes opening or closing
+    portal_timer: f32,
+    portal_unlocked: bool,
+    meanies_enabled: bool,
+    powerup_duration: Option<f32>,
+    barrel_dist: WeightedIndex<u32>,
+    next_id: u32,
+    /// Events since the presentation layer last took them
+    pub events: Vec<SimEvent>,
+}
+
+impl Sim {
+    pub fn new(config: SimConfig) -> Self {
+        let bricks_left = config
+            .bricks
+            .iter()
+            .filter(|b| b.is_some_and(|v| v != 13))
+            .count() as u32;
+        let mut sim = Sim {
+            status: SimStatus::Playing,
+            paddle: SimPaddle {
+                x: GAMEAREA_CENTER_X,
+                prev_x: GAMEAREA_CENTER_X,
+                variant: 0,
+                gun_cooldown: GUN_TIMER_MS as f32 / 1000.,
+            },
+            balls: Vec::new(),
+            bullets: Vec::new(),
+            barrels: Vec::new(),
+            meanies: Vec::new(),
+            effects: Vec::new(),
+            bricks: config.bricks,
+            bricks_left,
+            nlives: config.nlives,
+            score: config.score,
+            time_left: config.timer,
+            portal_state: PortalState::Closed,
+            portal_open: false,
+            portal_timer: 0.,
+            portal_unlocked: config.portal_unlocked,
+            meanies_enabled: config.meanies,
+            powerup_duration: config.powerup_duration,
+            barrel_dist: WeightedIndex::new(&config.barrel_weights).unwrap(),
+            next_id: 0,
+            events: Vec::new(),
+        };
+        sim.ball_serve();
+        if sim.portal_unlocked {
+            sim.portal_start(PortalState::Opening);
+        }
+        sim
+    }
+
+    /// Advances the simulation `dt` seconds
+    pub fn step(&mut self, dt: f32, input: &SimInput, rng: &mut impl Rng) {
+        if self.status != SimStatus::Playing {
+            return;
+        }
+        self.snapshot();
+        self.paddle_step(dt, input);
+        if self.status != SimStatus::Playing {
+            return;
+        }
+        self.balls_step(dt, rng);
+        self.bullets_step(dt, rng);
+        self.barrels_step(dt, rng);
+        self.effects_step(dt);
+        self.portal_step(dt);
+        self.meanies_step(dt, rng);
+        self.countdown_step(dt);
+        self.lives_step();
+    }
+
+    /// Remembers where everything is before moving it, so that the presentation
+    /// layer can interpolate between steps
+    fn snapshot(&mut self) {
+        self.paddle.prev_x = self.paddle.x;
+        for ball in &mut self.balls {
+            ball.prev_pos = ball.pos;
+        }
+        for bullet in &mut self.bullets {
+            bullet.prev_pos = bullet.pos;
+        }
+        for barrel in &mut self.barrels {
+            barrel.prev_pos = barrel.pos;
+        }
+        for meanie in &mut self.meanies {
+            meanie.prev_pos = meanie.pos;
+        }
+    }
+
+    fn new_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Creates a new ball attached to the paddle
+    fn ball_serve(&mut self) {
+        let id = self.new_id();
+        let pos = Vec2::new(
+            self.paddle.x + 5.,
+            PADDLE_Y + PADDLE_SIZES[self.paddle.variant].y / 2.0 + BALL_RADIUS,
+        );
+        self.balls.push(SimBall {
+            id,
+            pos,
+            prev_pos: pos,
+            impacts: 0,
+            impacts_since_paddle: 0,
+            combo: 0,
+            speed: BALL_INITIAL_SPEED,
+            pre_effect_speed: None,
+            direction: Vec2::from_angle(BALL_INITIAL_ANGLE),
+            travel: 0.,
+            caught: true,
+            in_collision: false,
+        });
+    }
+
+    fn paddle_step(&mut self, dt: f32, input: &SimInput) {
+        let paddle_speed = if input.boost {
+            PADDLE_MAX_SPEED
+        } else {
+            PADDLE_MIN_SPEED + (PADDLE_MAX_SPEED - PADDLE_MIN_SPEED) * input.throttle.clamp(0., 1.)
+        };
+
+        // Move paddle, towards the target if there is one
+        let mut delta_x = 0.0;
+        let step_x = match input.target_x {
+            Some(target_x) => {
+                let max_step = PADDLE_MAX_SPEED * dt;
+                (target_x - self.paddle.x).clamp(-max_step, max_step)
+            }
+            None => input.dir * paddle_speed * dt,
+        };
+        if step_x != 0.0 {
+            let mut new_x = self.paddle.x + step_x;
+            let min_x = BALLAREA_MINX + PADDLE_SIZES[self.paddle.variant].x / 2.0;
+            let mut max_x = BALLAREA_MAXX - PADDLE_SIZES[self.paddle.variant].x / 2.0;
+
+            // Change xmax and possibly level if portal is open
+            if self.portal_open {
+                max_x += 20.;
+                if new_x > max_x {
+                    // Time bonus for the seconds left on the countdown
+                    self.score += TIME_BONUS_POINTS * self.time_left.ceil() as u32;
+                    self.status = SimStatus::LevelExit;
+                    self.events.push(SimEvent::LevelExit);
+                    return;
+                }
+            }
+
+            new_x = new_x.clamp(min_x, max_x);
+            delta_x = new_x - self.paddle.x;
+            self.paddle.x = new_x;
+        }
+
+        // Move and/or release caught balls
+        for ball in self.balls.iter_mut().filter(|b| b.caught) {
+            ball.pos.x += delta_x;
+            ball.caught = !input.fire;
+        }
+
+        // Fire Gun
+        self.paddle.gun_cooldown -= dt;
+        if self.paddle.variant == PADDLE_GUN && input.fire && self.paddle.gun_cooldown <= 0. {
+            for gun_x in [GUN_LEFT_X, GUN_RIGHT_X] {
+                let id = self.new_id();
+                let pos = Vec2::new(self.paddle.x + gun_x, PADDLE_Y + GUN_Y);
+                self.bullets.push(SimBullet {
+                    id,
+                    pos,
+                    prev_pos: pos,
+                });
+            }
+            self.events.push(SimEvent::BulletsFired);
+            self.paddle.gun_cooldown = GUN_TIMER_MS as f32 / 1000.;
+        }
+    }
+
+    fn balls_step(&mut self, dt: f32, rng: &mut impl Rng) {
+        let mut balls = mem::take(&mut self.balls);
+        balls.retain_mut(|ball| self.ball_step(ball, dt, rng));
+        self.balls = balls;
+    }
+
+    /// Moves one ball and handles its collisions. Returns false if the ball was lost
+    fn ball_step(&mut self, ball: &mut SimBall, dt: f32, rng: &mut impl Rng) -> bool {
+        // Skip balls caught by the paddle
+        if ball.caught {
+            return true;
+        }
+        let p_pos = Vec2::new(self.paddle.x, PADDLE_Y);
+        let p_size = PADDLE_SIZES[self.paddle.variant];
+
+        // Move ball pixel by pixel, keeping the fraction of a pixel for the next step
+        ball.travel += ball.speed * dt;
+        let pixels = ball.travel.floor();
+        ball.travel -= pixels;
+        for _ in 0..pixels as i32 {
+            ball.pos += ball.direction;
+
+            // Check collision with walls
+            let mut wall_collisions: Vec<Vec2> = Vec::new();
+            if ball.pos.x - BALL_RADIUS < BALLAREA_MINX {
+                ball.direction.x = ball.direction.x.abs();
+                wall_collisions.push(Vec2::new(ball.pos.x - BALL_RADIUS, ball.pos.y));
+            }
+            if ball.pos.x + BALL_RADIUS > BALLAREA_MAXX {
+                ball.direction.x = -ball.direction.x.abs();
+                wall_collisions.push(Vec2::new(ball.pos.x + BALL_RADIUS, ball.pos.y));
+            }
+            if ball.pos.y + BALL_RADIUS > BALLAREA_MAXY {
+                ball.direction.y = -ball.direction.y.abs();
+                wall_collisions.push(Vec2::new(ball.pos.x, ball.pos.y + BALL_RADIUS));
+            }
+
+            ball.impacts += wall_collisions.len() as u32;
+            ball.impacts_since_paddle += wall_collisions.len() as u32;
+
+            // Ball lost through the bottom of the screen
+            if ball.pos.y + BALL_RADIUS < GAMEAREA_MINY {
+                return false;
+            }
+
+            for pos in wall_collisions {
+                self.events.push(SimEvent::WallHit {
+                    pos,
+                    speed: ball.speed,
+                });
+            }
+
+            // Check collision with paddle
+            if let Some(collides) = collision(Body::Round(ball.pos, BALL_RADIUS), p_pos, p_size) {
+                // ball.in_collision is a flag to avoid continuous collisions with the paddle
+                if !ball.in_collision {
+                    ball.impacts_since_paddle = 0;
+                    ball.combo = 0;
+                    match collides {
+                        Collision::Top => {
+                            if ball.direction.y < 0. {
+                                let slope: f32 = (PADDLE_MAX_ANGLE - PADDLE_MIN_ANGLE) / p_size.x;
+                                let p_maxx = p_pos.x + p_size.x / 2.0;
+                                let p_minx = p_maxx - p_size.x;
+                                let dx = (p_maxx - ball.pos.x).clamp(0., p_size.x);
+                                let angle = PADDLE_MIN_ANGLE + slope * dx;
+                                ball.direction = Vec2::from_angle(angle);
+                                if self.paddle.variant == PADDLE_MAGNET
+                                    && ball.pos.x >= p_minx + PADDLE_MAGNET_BORDER
+                                    && ball.pos.x <= p_maxx - PADDLE_MAGNET_BORDER
+                                {
+                                    ball.caught = true;
+                                    ball.pos.y = p_pos.y + p_size.y / 2.0 + BALL_RADIUS;
+                                    self.events.push(SimEvent::BallCaught(ball.pos));
+                                    break;
+                                }
+                            }
+                        }
+                        Collision::Left => ball.direction.x = -ball.direction.x.abs(),
+                        Collision::Right => ball.direction.x = ball.direction.x.abs(),
+                        Collision::Bottom => ball.direction.y = -ball.direction.y.abs(),
+                    }
+                    self.events.push(SimEvent::PaddleHit {
+                        pos: ball.pos,
+                        speed: ball.speed,
+                    });
+                    ball.in_collision = true;
+                }
+            } else {
+                ball.in_collision = false;
+            }
+
+            // Check collisions with bricks
+            let collisions = self.bricks_hit(
+                Body::Round(ball.pos, BALL_RADIUS),
+                ball.direction,
+                &mut ball.combo,
+                rng,
+            );
+
+            if !collisions.is_empty() {
+                self.events.push(SimEvent::BallHitBricks {
+                    pos: ball.pos,
+                    speed: ball.speed,
+                });
+            }
+
+            ball.impacts += collisions.len() as u32;
+            ball.impacts_since_paddle += collisions.len() as u32;
+
+            for c in collisions {
+                match c {
+                    Collision::Left => ball.direction.x = -ball.direction.x.abs(),
+                    Collision::Right => ball.direction.x = ball.direction.x.abs(),
+                    Collision::Bottom => ball.direction.y = -ball.direction.y.abs(),
+                    Collision::Top => ball.direction.y = ball.direction.y.abs(),
+                }
+            }
+        }
+
+        // Update ball speed
+        if ball.impacts > BALL_SPEEDUP_IMPACTS {
+            ball.impacts = 0;
+            ball.speed = (ball.speed * BALL_SPEED_DELTA).min(BALL_MAX_SPEED);
+            self.events
+                .push(SimEvent::SpeedChanged { speed: ball.speed });
+        }
+
+        // Nudge ball
+        if ball.impacts_since_paddle > BALL_NUDGE_IMPACTS {
+            ball.direction = nudge_ball(ball.direction, MULTIBALL_ANGLE_RANGE, rng);
+            ball.impacts_since_paddle = 0;
+            self.events.push(SimEvent::BallNudged { speed: ball.speed });
+        }
+        true
+    }
+
+    /// Checks collisions of a ball or bullet with the bricks around it,
+    /// damaging the bricks that were hit and scoring them with the `combo` multiplier
+    fn bricks_hit(
+        &mut self,
+        body: Body,
+        body_direction: Vec2,
+        combo: &mut u32,
+        rng: &mut impl Rng,
+    ) -> Vec<Collision> {
+        let mut collisions: Vec<Collision> = Vec::new();
+        let body_pos = match body {
+            Body::Rectangular(pos, _) => pos,
+            Body::Round(pos, _) => pos,
+        };
+
+        // Check collisions with bricks in the vicinity of the ball
+        let (body_r, body_c) = game_xy_to_rc(body_pos.x, body_pos.y);
+        let min_row = body_r.saturating_sub(1);
+        let max_row = (body_r + 1).min(GRID_ROWS - 1);
+        let min_col = body_c.saturating_sub(1);
+        let max_col = (body_c + 1).min(GRID_COLS - 1);
+
+        for r in min_row..=max_row {
+            for c in min_col..=max_col {
+                // Skip loop if no brick at this position
+                let grid_idx: usize = game_rc_to_idx(r, c);
+                let Some(variant) = self.bricks[grid_idx] else {
+                    continue;
+                };
+
+                // Check collision
+                let brick_pos: Vec2 = game_rc_to_xy(r, c).into();
+                let Some(side) = collision(body, brick_pos, BRICK_SIZE) else {
+                    continue;
+                };
+
+                if (side == Collision::Left && body_direction.x <= 0.)
+                    || (side == Collision::Right && body_direction.x >= 0.)
+                    || (side == Collision::Bottom && body_direction.y <= 0.)
+                    || (side == Collision::Top && body_direction.y >= 0.)
+                {
+                    continue;
+                }
+                collisions.push(side);
+                self.events.push(SimEvent::BrickHit {
+                    idx: grid_idx,
+                    variant,
+                });
+
+                // Score brick, more for each brick hit before going back to the paddle
+                if BRICK_POINTS[variant] > 0 {
+                    *combo += 1;
+                    let multiplier = (*combo).min(COMBO_MAX);
+                    if multiplier > 1 {
+                        self.events.push(SimEvent::Combo { multiplier });
+                    }
+                    self.score += BRICK_POINTS[variant] * multiplier;
+                }
+
+                if variant < 12 || variant == 14 {
+                    // Eliminate old brick
+                    self.bricks[grid_idx] = None;
+                    self.bricks_left -= 1;
+                    self.events.push(SimEvent::BrickDestroyed { idx: grid_idx });
+
+                    // Extra life if all bricks destroyed
+                    if self.bricks_left == 0 {
+                        self.nlives += 1;
+                        self.events.push(SimEvent::ExtraLife);
+                    }
+
+                    // Randomly spawn barrels upon impact
+                    self.barrel_spawn(brick_pos, rng);
+                } else if variant == 12 {
+                    self.bricks[grid_idx] = Some(11);
+                }
+            }
+        }
+        collisions
+    }
+
+    fn bullets_step(&mut self, dt: f32, rng: &mut impl Rng) {
+        let mut bullets = mem::take(&mut self.bullets);
+        bullets.retain_mut(|bullet| {
+            // Move bullets up
+            bullet.pos.y += BULLET_SPEED * dt;
+
+            // Explode bullet if hits brick or top wall
+            let bullet_top = bullet.pos.y + BULLET_SIZE.y / 2.0;
+            if bullet_top > BALLAREA_MAXY
+                || !self
+                    .bricks_hit(
+                        Body::Rectangular(bullet.pos, BULLET_SIZE),
+                        Vec2::Y,
+                        &mut 0,
+                        rng,
+                    )
+                    .is_empty()
+            {
+                self.events
+                    .push(SimEvent::BulletHit(Vec2::new(bullet.pos.x, bullet_top)));
+                return false;
+            }
+            true
+        });
+        self.bullets = bullets;
+    }
+
+    fn barrel_spawn(&mut self, pos: Vec2, rng: &mut impl Rng) {
+        if rng.random_bool(BARREL_CHANCE) {
+            let variant: usize = self.barrel_dist.sample(rng);
+            let id = self.new_id();
+            self.barrels.push(SimBarrel {
+                id,
+                pos,
+                prev_pos: pos,
+                variant,
+            });
+        }
+    }
+
+    fn barrels_step(&mut self, dt: f32, rng: &mut impl Rng) {
+        let paddle_pos = Vec2::new(self.paddle.x, PADDLE_Y);
+        let paddle_size = PADDLE_SIZES[self.paddle.variant];
+        let mut new_paddle_variant: usize = self.paddle.variant;
+        let mut multiball: u8 = 0;
+        let mut delta_speed: f32 = 1.0;
+        let mut barrel_collisions: u8 = 0;
+        let mut open_portal = false;
+        let mut caught: Vec<usize> = Vec::new();
+
+        let mut barrels = mem::take(&mut self.barrels);
+        barrels.retain_mut(|barrel| {
+            barrel.pos.y -= BARREL_SPEED * dt;
+            if collision(
+                Body::Rectangular(barrel.pos, BARREL_SIZE),
+                paddle_pos,
+                paddle_size,
+            )
+            .is_some()
+            {
+                match barrel.variant {
+                    0 => new_paddle_variant = PADDLE_LARGE,
+                    1 => new_paddle_variant = PADDLE_GUN,
+                    2 => new_paddle_variant = PADDLE_SMALL,
+                    3 => new_paddle_variant = PADDLE_MAGNET,
+                    4 => multiball += 1,
+                    5 => delta_speed *= BALL_SPEED_DELTA,
+                    6 => delta_speed /= BALL_SPEED_DELTA,
+                    7 => open_portal = true,
+                    8 => self.nlives += 1,
+                    9 if self.time_left > 0. => self.time_left += 30., // Extra Time
+                    _ => {}
+                }
+                self.events.push(SimEvent::BarrelCaught {
+                    variant: barrel.variant,
+                });
+                caught.push(barrel.variant);
+                barrel_collisions += 1;
+                return false;
+            }
+            barrel.pos.y >= GAMEAREA_MINY
+        });
+        self.barrels = barrels;
+
+        if barrel_collisions == 0 {
+            return;
+        }
+
+        // Start timers of the effects that wear off
+        for variant in caught {
+            self.effect_add(variant);
+        }
+
+        // Open portal right away, even if it was closed by the countdown
+        if open_portal
+            && matches!(
+                self.portal_state,
+                PortalState::Closed | PortalState::Closing
+            )
+        {
+            self.portal_start(PortalState::Opening);
+        }
+
+        // Update paddle
+        self.paddle.variant = new_paddle_variant;
+
+        // Update balls
+        let mut nballs = self.balls.len() as u32;
+        let mut new_balls: Vec<SimBall> = Vec::new();
+        let mut balls = mem::take(&mut self.balls);
+        balls.retain_mut(|ball| {
+            // Update speed
+            if delta_speed != 1.0 {
+                ball.speed = (ball.speed * delta_speed).clamp(BALL_MIN_SPEED, BALL_MAX_SPEED);
+                ball.impacts = 0;
+                // Only the speed of the last ball will be seen but that's OK
+                self.events
+                    .push(SimEvent::SpeedChanged { speed: ball.speed });
+            }
+
+            // unmagnetize if paddle was changed
+            if self.paddle.variant != PADDLE_MAGNET {
+                ball.caught = false;
+            }
+
+            // Multiball replaces the ball with 3 new balls
+            let mut m = multiball;
+            let mut keep = true;
+            while m > 0 && !ball.caught && nballs < MULTIBALL_MAX - 1 {
+                m -= 1;
+                for _ in 0..3 {
+                    let id = self.new_id();
+                    new_balls.push(SimBall {
+                        id,
+                        impacts: 0,
+                        impacts_since_paddle: 0,
+                        combo: 0,
+                        direction: nudge_ball(ball.direction, MULTIBALL_ANGLE_RANGE, rng),
+                        caught: false,
+                        in_collision: false,
+                        ..*ball
+                    });
+                }
+                keep = false;
+                nballs += 2;
+            }
+            keep
+        });
+        balls.append(&mut new_balls);
+        self.balls = balls;
+    }
+
+    /// Starts (or restarts) the timer of a paddle or speed barrel effect
+    fn effect_add(&mut self, variant: usize) {
+        let Some(duration) = self.powerup_duration else {
+            return;
+        };
+        match variant {
+            // Only the last paddle barrel is active
+            0..=3 => self.effects.retain(|e| e.variant > 3),
+            5 | 6 => {
+                // Only the last speed barrel is active, and it wears off back to
+                // the speed before the first one
+                self.effects.retain(|e| !matches!(e.variant, 5 | 6));
+                for ball in &mut self.balls {
+                    ball.pre_effect_speed.get_or_insert(ball.speed);
+                }
+            }
+            _ => return,
+        }
+        self.effects.push(SimEffect {
+            variant,
+            time_left: duration,
+        });
+    }
+
+    fn effects_step(&mut self, dt: f32) {
+        let mut expired: Vec<SimEffect> = Vec::new();
+        self.effects.retain_mut(|effect| {
+            effect.time_left -= dt;
+            if effect.time_left > 0. {
+                return true;
+            }
+            expired.push(effect.clone());
+            false
+        });
+
+        for effect in expired {
+            if effect.variant <= 3 {
+                // Back to the regular paddle, releasing caught balls
+                self.paddle.variant = 0;
+                for ball in &mut self.balls {
+                    ball.caught = false;
+                }
+                continue;
+            }
+
+            // Back to the speed before the effect
+            for ball in &mut self.balls {
+                if let Some(speed) = ball.pre_effect_speed.take() {
+                    ball.speed = speed;
+                    ball.impacts = 0;
+                    self.events.push(SimEvent::SpeedChanged { speed });
+                }
+            }
+        }
+    }
+
+    fn portal_start(&mut self, state: PortalState) {
+        self.portal_state = state;
+        self.portal_timer = PORTAL_FRAMES as f32 / PORTAL_FRAMERATE;
+        self.events.push(match state {
+            PortalState::Closing => SimEvent::PortalClosing,
+            _ => SimEvent::PortalOpening,
+        });
+    }
+
+    fn portal_step(&mut self, dt: f32) {
+        // Start opening portal if no bricks left and portal is closed
+        if self.bricks_left == 0 && self.portal_state == PortalState::Closed {
+            self.portal_start(PortalState::Opening);
+        }
+
+        // Portal fully open or closed at the end of the animation
+        self.portal_timer -= dt;
+        if self.portal_timer <= 0. {
+            match self.portal_state {
+                PortalState::Opening => {
+                    self.portal_state = PortalState::Open;
+                    self.portal_open = true;
+                }
+                PortalState::Closing => self.portal_state = PortalState::Closed,
+                _ => {}
+            }
+        }
+    }
+
+    fn meanie_destroy(&mut self, meanie: &SimMeanie, shot: bool) {
+        self.score += MEANIE_POINTS;
+        self.events.push(SimEvent::MeanieDestroyed {
+            pos: meanie.pos,
+            shot,
+        });
+    }
+
+    fn meanies_step(&mut self, dt: f32, rng: &mut impl Rng) {
+        let p_pos = Vec2::new(self.paddle.x, PADDLE_Y);
+        let p_size = PADDLE_SIZES[self.paddle.variant];
+
+        let mut meanies = mem::take(&mut self.meanies);
+        meanies.retain_mut(|meanie| {
+            meanie.age += dt;
+            if meanie.pos.y > MEANIES_MAXY {
+                meanie.pos.y -= dt * meanie.speed;
+            } else {
+                // Meanie follows a random walk down the screen
+                meanie.angle += rng.random_range(-PI / 16.0..=PI / 16.0);
+                if meanie.angle < MEANIES_MIN_ANGLE {
+                    meanie.angle = 2. * MEANIES_MIN_ANGLE - meanie.angle;
+                } else if meanie.angle > MEANIES_MAX_ANGLE {
+                    meanie.angle = 2. * MEANIES_MAX_ANGLE - meanie.angle;
+                }
+
+                // meanies are slow so we don't need to move them pixel by pixel
+                meanie.pos += dt * meanie.speed * Vec2::from_angle(meanie.angle);
+                if meanie.pos.x < MEANIES_MINX {
+                    meanie.pos.x = 2. * MEANIES_MINX - meanie.pos.x;
+                    meanie.angle = PI - meanie.angle;
+                } else if meanie.pos.x > MEANIES_MAXX {
+                    meanie.pos.x = 2. * MEANIES_MAXX - meanie.pos.x;
+                    meanie.angle = -PI - meanie.angle;
+                }
+
+                // Bottom border
+                if meanie.pos.y < MEANIES_MINY {
+                    return false;
+                }
+            }
+
+            // Meanie size depends on the animation frame
+            let meanie_size = MEANIES_SIZES[meanie.variant][meanie.frame()];
+
+            // Check collision with paddle
+            let body = Body::Rectangular(p_pos, p_size);
+            if collision(body, meanie.pos, meanie_size).is_some() {
+                self.meanie_destroy(meanie, false);
+                return false;
+            }
+
+            // Check collision with bullet
+            if let Some(b) = self.bullets.iter().position(|bullet| {
+                let body = Body::Rectangular(bullet.pos, BULLET_SIZE);
+                collision(body, meanie.pos, meanie_size).is_some()
+            }) {
+                self.bullets.remove(b);
+                self.meanie_destroy(meanie, true);
+                return false;
+            }
+
+            // Check collision with balls (skip balls caught by the paddle)
+            if let Some(ball) = self.balls.iter_mut().find(|ball| {
+                let body = Body::Round(ball.pos, BALL_RADIUS);
+                !ball.caught && collision(body, meanie.pos, meanie_size).is_some()
+            }) {
+                ball.direction.y *= -1.;
+                self.meanie_destroy(meanie, false);
+                return false;
+            }
+            true
+        });
+        self.meanies = meanies;
+
+        // Spawn new meanie
+        if self.meanies.len() < MEANIES_MAX as usize && self.meanies_enabled {
+            let spawn_prob: f32 = MEANIES_PER_SECOND * dt;
+            if rng.random::<f32>() < spawn_prob {
+                let variant: usize = rng.random_range(0..MEANIES_TYPES);
+                let portal: usize = rng.random_range(0..2);
+                let id = self.new_id();
+                let pos = Vec2::new(MEANIES_PORTAL_X[portal], MEANIES_PORTAL_Y);
+                self.meanies.push(SimMeanie {
+                    id,
+                    pos,
+                    prev_pos: pos,
+                    variant,
+                    speed: MEANIES_SPEED,
+                    angle: -FRAC_PI_2,
+                    age: 0.,
+                });
+            }
+        }
+    }
+
+    fn countdown_step(&mut self, dt: f32) {
+        if self.time_left <= 0. {
+            return;
+        }
+        self.time_left = (self.time_left - dt).max(0.);
+        if self.time_left > 0. {
+            return;
+        }
+        self.events.push(SimEvent::CountdownFinished);
+
+        // Close portal if level is unlocked and countdown done
+        if self.portal_unlocked {
+            self.portal_open = false;
+            self.portal_start(PortalState::Closing);
+        }
+    }
+
+    fn lives_step(&mut self) {
+        // if last ball then take life
+        if !self.balls.is_empty() {
+            return;
+        }
+        self.nlives = self.nlives.saturating_sub(1);
+
+        // if lives is zero, end game
+        if self.nlives == 0 {
+            self.status = SimStatus::GameOver;
+            self.events.push(SimEvent::GameOver);
+            return;
+        }
+
+        // if not last life, reset paddle and effects and serve a new ball
+        let paddle_pos = Vec2::new(self.paddle.x, PADDLE_Y);
+        self.effects.clear();
+        self.paddle.variant = 0;
+        self.paddle.x = GAMEAREA_CENTER_X;
+        self.paddle.prev_x = GAMEAREA_CENTER_X;
+        self.ball_serve();
+        self.events.push(SimEvent::LifeLost(paddle_pos));
+    }
+}
+
+/// Rotates a direction by a random angle, avoiding directions that are too
+/// close to horizontal or vertical
+pub fn nudge_ball(direction: Vec2, max_rotation: f32, rng: &mut impl Rng) -> Vec2 {
+    let rotation = Vec2::from_angle(rng.random_range(-max_rotation..max_rotation));
+    let mut new_direction = direction.rotate(rotation);
+    if new_direction.x.abs() < 0.05 || new_direction.y.abs() < 0.05 {
+        let rotation = Vec2::from_angle(0.1);
+        new_direction = new_direction.rotate(rotation);
+    }
+    new_direction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const DT: f32 = 1. / SIM_TICK_HZ as f32;
+
+    /// Level with bricks of the first variant in the given (row, column) slots
+    fn config(bricks: &[(usize, usize)], nlives: u32) -> SimConfig {
+        let mut grid = vec![None; GRID_ROWS * GRID_COLS];
+        for &(r, c) in bricks {
+            grid[game_rc_to_idx(r, c)] = Some(0);
+        }
+        SimConfig {
+            bricks: grid,
+            timer: 60.,
+            nlives,
+            score: 0,
+            portal_unlocked: false,
+            meanies: false,
+            barrel_weights: BARREL_WEIGHTS.to_vec(),
+            powerup_duration: None,
+        }
+    }
+
+    /// Steps the simulation until `done` or for `secs` seconds, with the input
+    /// given for every step. Returns the events of all the steps
+    fn run(
+        sim: &mut Sim,
+        rng: &mut StdRng,
+        secs: f32,
+        input: impl Fn(u32) -> SimInput,
+        done: impl Fn(&SimEvent) -> bool,
+    ) -> Vec<SimEvent> {
+        let mut events = Vec::new();
+        for step in 0..(secs / DT) as u32 {
+            sim.step(DT, &input(step), rng);
+            let finished = sim.events.iter().any(&done);
+            events.append(&mut sim.events);
+            if finished {
+                break;
+            }
+        }
+        events
+    }
+
+    /// Releases the ball and keeps the paddle on the left, away from it
+    fn dodge(step: u32) -> SimInput {
+        SimInput {
+            dir: -1.,
+            boost: true,
+            fire: step == 0,
+            ..SimInput::default()
+        }
+    }
+
+    fn fire(_: u32) -> SimInput {
+        SimInput {
+            fire: true,
+            ..SimInput::default()
+        }
+    }
+
+    #[test]
+    fn ball_waits_on_the_paddle() {
+        let mut sim = Sim::new(config(&[(0, 0)], NLIVES));
+        let mut rng = StdRng::seed_from_u64(1);
+        let pos = sim.balls[0].pos;
+        run(&mut sim, &mut rng, 2., |_| SimInput::default(), |_| false);
+        assert!(sim.balls[0].caught);
+        assert_eq!(sim.balls[0].pos, pos);
+    }
+
+    #[test]
+    fn ball_breaks_bricks() {
+        let bricks: Vec<(usize, usize)> = (0..GRID_ROWS / 2)
+            .flat_map(|r| (0..GRID_COLS).map(move |c| (r, c)))
+            .collect();
+        let mut sim = Sim::new(config(&bricks, NLIVES));
+        let mut rng = StdRng::seed_from_u64(2);
+        let events = run(&mut sim, &mut rng, 10., fire, |event| {
+            matches!(event, SimEvent::BrickDestroyed { .. })
+        });
+        let Some(SimEvent::BrickHit { idx, variant: 0 }) = events
+            .iter()
+            .find(|event| matches!(event, SimEvent::BrickHit { .. }))
+        else {
+            panic!("no brick hit in {events:?}");
+        };
+        assert!(events.contains(&SimEvent::BrickDestroyed { idx: *idx }));
+        assert_eq!(sim.bricks[*idx], None);
+
+        // A corner can break two bricks in the same step
+        let destroyed = events
+            .iter()
+            .filter(|event| matches!(event, SimEvent::BrickDestroyed { .. }))
+            .count();
+        assert_eq!(sim.bricks_left as usize, bricks.len() - destroyed);
+        assert!(sim.score >= destroyed as u32 * BRICK_POINTS[0]);
+    }
+
+    #[test]
+    fn lost_ball_takes_a_life() {
+        let mut sim = Sim::new(config(&[(0, 0)], NLIVES));
+        let mut rng = StdRng::seed_from_u64(3);
+        let events = run(&mut sim, &mut rng, 60., dodge, |event| {
+            matches!(event, SimEvent::LifeLost(_))
+        });
+        assert!(matches!(events.last(), Some(SimEvent::LifeLost(_))));
+        assert_eq!(sim.nlives, NLIVES - 1);
+        assert_eq!(sim.status, SimStatus::Playing);
+        assert_eq!(sim.balls.len(), 1);
+        assert!(sim.balls[0].caught);
+    }
+
+    #[test]
+    fn last_life_ends_the_game() {
+        let mut sim = Sim::new(config(&[(0, 0)], 1));
+        let mut rng = StdRng::seed_from_u64(4);
+        let events = run(&mut sim, &mut rng, 60., dodge, |event| {
+            *event == SimEvent::GameOver
+        });
+        assert_eq!(events.last(), Some(&SimEvent::GameOver));
+        assert!(!events.iter().any(|e| matches!(e, SimEvent::LifeLost(_))));
+        assert_eq!(sim.nlives, 0);
+        assert_eq!(sim.status, SimStatus::GameOver);
+
+        // Nothing happens after the end of the game
+        sim.step(DT, &fire(0), &mut rng);
+        assert!(sim.events.is_empty());
+    }
+
+    #[test]
+    fn no_lives_ends_the_game() {
+        let mut sim = Sim::new(config(&[(0, 0)], 0));
+        let mut rng = StdRng::seed_from_u64(5);
+        let events = run(&mut sim, &mut rng, 60., dodge, |event| {
+            *event == SimEvent::GameOver
+        });
+        assert_eq!(events.last(), Some(&SimEvent::GameOver));
+        assert_eq!(sim.nlives, 0);
+    }
+
+    #[test]
+    fn portal_opens_without_bricks() {
+        let mut sim = Sim::new(config(&[], NLIVES));
+        let mut rng = StdRng::seed_from_u64(6);
+        let events = run(&mut sim, &mut rng, 1., |_| SimInput::default(), |_| false);
+        assert_eq!(events, vec![SimEvent::PortalOpening]);
+        assert_eq!(sim.portal_state, PortalState::Open);
+        assert!(sim.portal_open);
+
+        // Leaving through the portal scores the seconds left
+        let time_left = sim.time_left;
+        let right = |_| SimInput {
+            dir: 1.,
+            boost: true,
+            ..SimInput::default()
+        };
+        let events = run(&mut sim, &mut rng, 5., right, |event| {
+            *event == SimEvent::LevelExit
+        });
+        assert_eq!(events, vec![SimEvent::LevelExit]);
+        assert_eq!(sim.status, SimStatus::LevelExit);
+        assert!(sim.time_left < time_left);
+        assert_eq!(sim.score, TIME_BONUS_POINTS * sim.time_left.ceil() as u32);
+    }
+
+    #[test]
+    fn portal_closes_with_the_countdown() {
+        let mut config = config(&[(0, 0)], NLIVES);
+        config.portal_unlocked = true;
+        config.timer = 1.;
+        let mut sim = Sim::new(config);
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(sim.events, vec![SimEvent::PortalOpening]);
+        sim.events.clear();
+        let events = run(&mut sim, &mut rng, 2., |_| SimInput::default(), |_| false);
+        assert_eq!(
+            events,
+            vec![SimEvent::CountdownFinished, SimEvent::PortalClosing]
+        );
+        assert!(!sim.portal_open);
+    }
+
+    #[test]
+    fn speed_barrels_wear_off() {
+        let mut config = config(&[(0, 0)], NLIVES);
+        config.powerup_duration = Some(1.);
+        let mut sim = Sim::new(config);
+        let mut rng = StdRng::seed_from_u64(9);
+        let speed = sim.balls[0].speed;
+
+        // Fast barrels dropped on the paddle, the second one before the first wears off
+        fn catch(sim: &mut Sim, rng: &mut StdRng) {
+            let pos = Vec2::new(sim.paddle.x, PADDLE_Y);
+            let id = sim.new_id();
+            sim.barrels.push(SimBarrel {
+                id,
+                pos,
+                prev_pos: pos,
+                variant: 5,
+            });
+            sim.step(DT, &SimInput::default(), rng);
+            sim.events.clear();
+        }
+        catch(&mut sim, &mut rng);
+        assert!(sim.balls[0].speed > speed);
+        run(&mut sim, &mut rng, 0.5, |_| SimInput::default(), |_| false);
+        catch(&mut sim, &mut rng);
+        assert!(sim.balls[0].speed > speed * BALL_SPEED_DELTA);
+        assert_eq!(sim.effects.len(), 1);
+
+        // The ball gets back the speed it had before the first barrel
+        let events = run(
+            &mut sim,
+            &mut rng,
+            2.,
+            |_| SimInput::default(),
+            |event| matches!(event, SimEvent::SpeedChanged { .. }),
+        );
+        assert_eq!(events.last(), Some(&SimEvent::SpeedChanged { speed }));
+        assert!(sim.effects.is_empty());
+        assert_eq!(sim.balls[0].speed, speed);
+    }
+
+    #[test]
+    fn same_seed_and_input_give_the_same_game() {
+        let bricks: Vec<(usize, usize)> = (0..GRID_ROWS / 2)
+            .flat_map(|r| (0..GRID_COLS).map(move |c| (r, c)))
+            .collect();
+        let play = |seed| {
+            let mut sim = Sim::new(config(&bricks, NLIVES));
+            let mut rng = StdRng::seed_from_u64(seed);
+            let events = run(&mut sim, &mut rng, 30., dodge, |_| false);
+            (events, sim.score, sim.bricks)
+        };
+        assert_eq!(play(8), play(8));
+    }
+}
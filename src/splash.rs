@@ -1,11 +1,11 @@
 use super::{GameState, despawn_all};
 use crate::consts::*;
 use crate::game::*;
+use crate::level::*;
+use crate::replay::*;
 use crate::save::*;
 use bevy::prelude::*;
-use rand::distr::weighted::WeightedIndex;
 use std::thread;
-use users::{get_current_uid, get_user_by_uid};
 
 const SPLASH_TIMER: f32 = 5.;
 
@@ -49,33 +49,57 @@ fn splash_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn splash_timer(
     countdown_comp: Single<&mut Countdown>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut game: ResMut<Game>,
+    asset_server: Res<AssetServer>,
+    campaigns: Res<Assets<Campaign>>,
     time: Res<Time>,
+    player: Option<Res<ReplayPlayer>>,
+    mut load_failed: Local<bool>,
 ) {
     let mut countdown = countdown_comp.into_inner();
-    if countdown.timer.tick(time.delta()).is_finished() {
+    if !countdown.timer.tick(time.delta()).is_finished() {
+        return;
+    }
+
+    // Levels must be loaded before leaving the splash screen
+    if !asset_server.is_loaded_with_dependencies(&game.campaign) {
+        if !*load_failed
+            && asset_server
+                .recursive_dependency_load_state(&game.campaign)
+                .is_failed()
+        {
+            error!("Could not load levels from {CAMPAIGN_FILE}");
+            *load_failed = true;
+        }
+        return;
+    }
+    if let Some(campaign) = campaigns.get(&game.campaign) {
+        game.nlevels = campaign.levels.len().min(MAX_LEVELS);
         game_state.set(GameState::Menu);
+
+        // Replays given with -p start right away
+        if let Some(player) = player {
+            game.current_level = player.replay.level.min(game.nlevels.saturating_sub(1));
+            game_state.set(GameState::Game);
+        }
     }
 }
 
 pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let user = get_user_by_uid(get_current_uid()).unwrap();
-    let mut username = String::from(user.name().to_string_lossy());
-    username.truncate(USERNAME_LEN);
-    username = format!("{:X<USERNAME_LEN$}", username.to_uppercase());
-
     let mut game = Game {
         grid: vec![None; GRID_ROWS * GRID_COLS],
         nlives: NLIVES,
-        nballs: 0,
-        nmeanies: 0,
+        score: 0,
+        play_time: 0.,
+        level_play_time: 0.,
         current_level: 0,
-        bricks_left: 0,
-        portal_open: false,
+        nlevels: 0,
         seconds_left: 0.,
-	avg_delta: 1./60., // 60 FPS, will converge quickly to actual FPS
+        playtest: false,
         h_menu_bg: asset_server.load("images/background_menu.png"),
         h_paddles: vec![
             asset_server.load("images/bat00.png"),
@@ -87,17 +111,19 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
         h_bricks: Vec::new(),
         h_ball: asset_server.load("images/ball0.png"),
         h_bullet: asset_server.load("images/bullet0.png"),
-        h_backgrounds: Vec::new(),
         h_brick_frames: Vec::new(),
         h_barrel_frames: Vec::new(),
         h_portal_frames: Vec::new(),
         h_portal_right: asset_server.load("images/portal_right_frame.png"),
         h_portal_top_left: asset_server.load("images/portal_top_left_frame.png"),
         h_portal_top_right: asset_server.load("images/portal_top_right_frame.png"),
+        h_meanies_portal_frames: Vec::new(),
         h_ball_impact_frames: Vec::new(),
         h_bullet_impact_frames: Vec::new(),
+        h_gameover_frames: Vec::new(),
+        h_start_frames: Vec::new(),
         h_meanies_frames: Vec::new(),
-        // h_meanies_shadows: Vec::new(),
+        h_meanies_shadows: Vec::new(),
         h_paddle_shadows: vec![
             asset_server.load("images/bats00.png"),
             asset_server.load("images/bats33.png"), // Extended
@@ -109,14 +135,29 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
         h_ball_shadow: asset_server.load("images/balls.png"),
         h_barrel_shadow: asset_server.load("images/barrels.png"),
         h_logo: asset_server.load("images/logo_small.png"),
-        barrel_dist: WeightedIndex::new(BARREL_WEIGHTS).unwrap(),
+        h_life: asset_server.load("images/life.png"),
+        h_blank: asset_server.load("images/blank.png"),
+        campaign: asset_server.load(CAMPAIGN_FILE),
+        barrel_weights: BARREL_WEIGHTS.to_vec(),
 
         music_main_theme: asset_server.load("music/title_theme.ogg"),
         music_arkanoid: asset_server.load("music/arkanoid.ogg"),
-        sound_hit_wall: asset_server.load("sounds/hit_wall0.ogg"),
-        sound_hit_brick: asset_server.load("sounds/hit_brick0.ogg"),
+        sound_hit_wall: vec![
+            asset_server.load("sounds/hit_wall0.ogg"),
+            asset_server.load("sounds/hit_fast0.ogg"),
+            asset_server.load("sounds/hit_veryfast0.ogg"),
+        ],
+        sound_hit_brick: vec![
+            asset_server.load("sounds/hit_brick0.ogg"),
+            asset_server.load("sounds/hit_fast0.ogg"),
+            asset_server.load("sounds/hit_veryfast0.ogg"),
+        ],
         sound_fire_bullet: asset_server.load("sounds/laser0.ogg"),
-        sound_paddle: asset_server.load("sounds/hit_fast0.ogg"),
+        sound_paddle: vec![
+            asset_server.load("sounds/hit_medium0.ogg"),
+            asset_server.load("sounds/hit_fast0.ogg"),
+            asset_server.load("sounds/hit_veryfast0.ogg"),
+        ],
         sound_magnet: asset_server.load("sounds/ball_stick0.ogg"),
         sound_bullet_hit: vec![
             asset_server.load("sounds/bullet_hit0.ogg"),
@@ -124,8 +165,10 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
             asset_server.load("sounds/bullet_hit2.ogg"),
             asset_server.load("sounds/bullet_hit3.ogg"),
         ],
-        sound_chime: asset_server.load("sounds/chime.ogg"),
         sound_start: asset_server.load("sounds/arkanoid_start.ogg"),
+        sound_start_game: asset_server.load("sounds/start_game0.ogg"),
+        sound_lose_life: asset_server.load("sounds/lose_life0.ogg"),
+        sound_game_over: asset_server.load("sounds/game_over0.ogg"),
         sound_portal: asset_server.load("sounds/portal_exit0.ogg"),
         sound_barrels: vec![
             asset_server.load("sounds/bat_extend0.ogg"),
@@ -139,21 +182,18 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
             asset_server.load("sounds/extra_life0.ogg"),
             asset_server.load("sounds/powerup0.ogg"), // EXTRA TIME
         ],
-        levels_unlocked: vec![String::new(); NLEVELS],
-        secrets_unlocked: vec![String::new(); NLEVELS],
-        secrets_generated: vec![String::new(); NLEVELS],
-        username,
+        sound_menu: asset_server.load("sounds/bounce_synth0.ogg"),
+        levels_unlocked: vec![String::new(); MAX_LEVELS],
+        secrets_unlocked: vec![String::new(); NSECRETS],
+        secrets_generated: vec![String::new(); NSECRETS],
+        player_id: String::new(),
+        handle: String::new(),
     };
 
     // This is a hack to give time to asset_server to load stuff
     // before loading more stuff and running out of file handlers
     thread::sleep(std::time::Duration::from_millis(2000));
 
-    for l in 0..NLEVELS {
-        let filepath = format!("images/arena{l:x}.png");
-        game.h_backgrounds.push(asset_server.load(filepath));
-    }
-
     for b in 0..BRICK_TYPES {
         let filepath = format!("images/brick{b:x}.png");
         game.h_bricks.push(asset_server.load(filepath));
@@ -197,6 +237,14 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
         game.h_portal_frames.push(asset_server.load(filepath));
     }
 
+    for p in 0..MEANIES_PORTAL_X.len() {
+        game.h_meanies_portal_frames.push(Vec::new());
+        for f in 0..MEANIES_PORTAL_FRAMES {
+            let filepath = format!("images/portal_meanie{p:x}{f:x}.png");
+            game.h_meanies_portal_frames[p].push(asset_server.load(filepath));
+        }
+    }
+
     for f in 0..BALL_IMPACT_FRAMES {
         let filepath = format!("images/impactc{f:x}.png");
         game.h_ball_impact_frames.push(asset_server.load(filepath));
@@ -208,18 +256,32 @@ pub fn load_game_resources(mut commands: Commands, asset_server: Res<AssetServer
             .push(asset_server.load(filepath));
     }
 
-    for m in 0..MEANIES_TYPES {
+    for f in 0..GAMEOVER_FRAMES {
+        let filepath = format!("images/gameover{f:x}.png");
+        game.h_gameover_frames.push(asset_server.load(filepath));
+    }
+
+    for f in 0..START_FRAMES {
+        let filepath = format!("images/start{f:x}.png");
+        game.h_start_frames.push(asset_server.load(filepath));
+    }
+
+    for (m, &nframes) in MEANIES_NFRAMES.iter().enumerate() {
         game.h_meanies_frames.push(Vec::new());
-        // game.h_meanies_shadows.push(Vec::new());
-        for f in 0..MEANIES_NFRAMES[m] {
+        game.h_meanies_shadows.push(Vec::new());
+        for f in 0..nframes {
             let filepath = format!("images/meanie{m:x}{f:x}.png");
             game.h_meanies_frames[m].push(asset_server.load(filepath));
-            // let filepath = format!("images/meanies{m:x}{f:x}.png");
-            // game.h_meanies_shadows[m].push(asset_server.load(filepath));
+            let filepath = format!("images/meanies{m:x}{f:x}.png");
+            game.h_meanies_shadows[m].push(asset_server.load(filepath));
         }
     }
 
-    game_load(&mut game);
+    // Progress, settings and scores of the current profile
+    let save = game_load(&mut game);
+    commands.insert_resource(save.settings);
+    commands.insert_resource(save.highscores);
+    commands.insert_resource(save.statistics);
 
     commands.insert_resource(game);
 }
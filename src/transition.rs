@@ -1,10 +1,19 @@
 use super::GameState;
+use crate::animation::*;
+use crate::audio::*;
+use crate::codes::*;
 use crate::consts::*;
+use crate::controls::*;
 use crate::game::*;
+use crate::highscores::*;
+use crate::level::*;
+use crate::save::*;
 use crate::shop::*;
 use rand::seq::*;
 use crate::*;
 
+use bevy::input::ButtonState;
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
 use std::time::Duration;
 
@@ -15,6 +24,8 @@ pub struct Transition {
     pub texts: Vec<String>,
     pub colors: Vec<Color>,
     pub end_game: bool,
+    /// Name being typed for a new high score, once all texts were shown
+    pub name_entry: Option<String>,
 }
 
 pub fn transition_plugin(app: &mut App) {
@@ -22,21 +33,39 @@ pub fn transition_plugin(app: &mut App) {
         .add_systems(OnExit(GameState::Transition), despawn_all::<Transition>)
         .add_systems(
             Update,
-            transition_update.run_if(in_state(GameState::Transition)),
+            (transition_update, animate).run_if(in_state(GameState::Transition)),
         );
 }
 
 // When entering this function, game.current_level is set to the
 // level that we are entering, not the one we are leaving
-pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
+#[allow(clippy::too_many_arguments)]
+pub fn transition_enter(
+    mut commands: Commands,
+    mut game: ResMut<Game>,
+    mut rng: ResMut<GameRng>,
+    campaigns: Res<Assets<Campaign>>,
+    levels: Res<Assets<Level>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut audio: Audio,
+    mut statistics: ResMut<Statistics>,
+) {
+    // Levels play-tested from the editor return to it when they end
+    if game.playtest {
+        game.nlives = NLIVES;
+        game_state.set(GameState::Editor);
+        return;
+    }
+
     let mut texts: Vec<String> = Vec::new();
     let mut colors: Vec<Color> = Vec::new();
     let mut end_game = false;
 
     // Check if game over
     if game.nlives == 0 {
+        audio.play(AudioBus::Sfx, &game.sound_game_over);
         commands.spawn((
-            Text2d::new("Game Over"),
+            Text2d::new(""),
             TextColor(Color::WHITE),
             TextFont {
                 font_size: 20.,
@@ -47,26 +76,47 @@ pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
             Transition {
                 idx: 0,
                 timer: Timer::new(Duration::from_secs(TRANSITION_BANNER_SECS), TimerMode::Once),
-                texts: vec!["Game Over".to_string()],
+                texts: vec![format!("\n\n\n\nScore: {}", game.score)],
                 colors: vec![Color::WHITE],
                 end_game: true,
+                name_entry: None,
             },
+            children![(
+                Sprite::from_image(game.h_gameover_frames[0].clone()),
+                Transform::from_xyz(0., 40., 0.),
+                Animation {
+                    timer: Timer::from_seconds(1.0 / GAMEOVER_FRAMERATE, TimerMode::Repeating),
+                    frozen: false,
+                    variant: AnimationType::Repeat,
+                    frames: game.h_gameover_frames.clone(),
+                    current_frame: 0,
+                    reverse: false,
+                    velocity: Vec2::ZERO,
+                },
+            )],
         ));
 
+        statistics.game_ended(game.score, game.play_time);
+        statistics.save();
+
         // Reset for new game
         game.nlives = NLIVES;
         return;
     }
 
+    if game.nlives > 0 && game.current_level > 0 {
+        statistics.levels_completed += 1;
+    }
+
     // Unblock secrets from recently completed level
     if game.nlives > 0 && game.current_level > 0 && game.seconds_left > 0. {
         let prev_level = game.current_level - 1;
-        if game.secrets_generated[prev_level].is_empty() {
-            shop_code_generate_new(&mut game, 'X', prev_level as u8);
+        if prev_level < NSECRETS && game.secrets_generated[prev_level].is_empty() {
+            shop_code_generate_new(&mut game, CodeKind::Secret, prev_level);
             texts.push("You Discovered a Secret\n(check the Shop)".to_string());
             colors.push(Color::Srgba(Srgba::new(1.0, 1.0, 1.0, 1.0)));
         } else if game.levels_unlocked[prev_level].is_empty() {
-            shop_code_generate_new(&mut game, 'L', prev_level as u8);
+            shop_code_generate_new(&mut game, CodeKind::Level, prev_level);
             texts.push("You permanently unlocked the\nportal in this level...".to_string());
             colors.push(Color::Srgba(Srgba::new(1.0, 1.0, 1.0, 1.0)));
         };
@@ -81,7 +131,8 @@ pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
         );
         colors.push(Color::WHITE);
         end_game = true;
-    } else if game.current_level == NLEVELS && !secret_is_unlocked(Secret::Credits as usize, &game)
+    } else if game.current_level == game.nlevels
+        && !secret_is_unlocked(Secret::Credits as usize, &game)
     {
         texts.push(
             "You completed the Game but tales of those \n\
@@ -92,7 +143,7 @@ pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
         );
         colors.push(Color::WHITE);
         end_game = true;
-    } else if game.current_level == NLEVELS && !game.secrets_unlocked[7].is_empty() {
+    } else if game.current_level == game.nlevels && !game.secrets_unlocked[7].is_empty() {
         texts.push(
             "You completed the game! Congratulations!\n\
 	     Make sure to visit the CREDITs screen from the menu!\n"
@@ -100,18 +151,24 @@ pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
         );
         colors.push(Color::WHITE);
         end_game = true;
-    } else {
+    } else if let Some(level) =
+        campaign_level(&game.campaign, game.current_level, &campaigns, &levels)
+    {
         // Next level banner
-	let mut text : String = LEVEL_TITLES[game.current_level].to_string();
-	text.push_str("\n\n\n");
-	text.push_str(HINTS.choose(&mut rand::rng()).unwrap());
+        let mut text: String = level.title.clone();
+        text.push_str("\n\n\n");
+        text.push_str(HINTS.choose(&mut rng.rng).unwrap());
         texts.push(format!(
             "Level #{}\n{}",
             game.current_level + 1,
             text,
         ));
-        colors.push(Color::Srgba(LEVEL_COLORS[game.current_level]));
+        colors.push(Color::Srgba(level.color));
+    }
+    if end_game {
+        statistics.game_ended(game.score, game.play_time);
     }
+    statistics.save();
 
     commands.spawn((
         Text2d::new(""),
@@ -128,17 +185,70 @@ pub fn transition_enter(mut commands: Commands, mut game: ResMut<Game>) {
             texts,
             colors,
             end_game,
+            name_entry: None,
         },
     ));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn transition_update(
     transition_comp: Single<(&mut Text2d, &mut TextColor, &mut Transition)>,
     mut game_state: ResMut<NextState<GameState>>,
     time: Res<Time>,
+    mut keyboard: MessageReader<KeyboardInput>,
+    actions: Actions,
+    mut highscores: ResMut<HighScores>,
+    mode: Res<GameMode>,
+    game: Res<Game>,
+    mut banners: Query<&mut Visibility, With<Animation>>,
 ) {
     let (mut text, mut color, mut transition) = transition_comp.into_inner();
 
+    // Name entry for a new high score
+    if let Some(name) = &mut transition.name_entry {
+        if actions.just_pressed(Action::Back) {
+            game_state.set(GameState::Menu);
+            return;
+        }
+        if actions.just_pressed(Action::Confirm) && !name.is_empty() {
+            highscores.insert(
+                *mode,
+                HighScore {
+                    name: name.clone(),
+                    score: game.score,
+                    level: (game.current_level + 1).min(game.nlevels),
+                    seconds: game.play_time as u32,
+                    date: highscore_date(),
+                },
+            );
+            highscores.save();
+            game_state.set(GameState::Menu);
+            return;
+        }
+        if actions.just_pressed(Action::Delete) {
+            name.pop();
+        }
+        for ev in keyboard.read() {
+            if ev.state == ButtonState::Released {
+                continue;
+            }
+            if let Key::Character(input) = &ev.logical_key {
+                for c in input.chars() {
+                    if (c.is_ascii_alphanumeric() || c == ' ') && name.len() < HIGHSCORE_NAME_LEN {
+                        name.push(c.to_ascii_uppercase());
+                    }
+                }
+            }
+        }
+        *text = Text2d::new(format!(
+            "NEW HIGH SCORE: {}\n\nEnter your name:\n{}_\n\nPress {} to confirm",
+            game.score,
+            name,
+            actions.controls.key_name(Action::Confirm)
+        ));
+        return;
+    }
+
     transition.timer.tick(time.delta());
     if transition.timer.just_finished() || transition.idx == 0 {
         if transition.idx < transition.texts.len() {
@@ -147,6 +257,14 @@ pub fn transition_update(
             *color = TextColor(transition.colors[transition.idx]);
             transition.idx += 1;
             transition.timer.reset();
+        } else if transition.end_game && highscores.qualifies(*mode, game.score) {
+            // Ignore the keys pressed during the banners
+            keyboard.clear();
+            for mut visibility in &mut banners {
+                *visibility = Visibility::Hidden;
+            }
+            *color = TextColor(Color::WHITE);
+            transition.name_entry = Some(game.handle.clone());
         } else if transition.end_game {
             game_state.set(GameState::Menu);
         } else {